@@ -1,72 +1,146 @@
-use std::sync::{Arc, RwLock};
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::ptr::null_mut;
+use std::sync::{mpsc, Arc, RwLock};
 use std::thread;
-use enigo::{Button, Coordinate, Enigo, Mouse, Settings, Direction::{Press, Release}};
-use rodio;
+use enigo::{Button, Coordinate, Enigo, Key, Keyboard, Mouse, Settings, Direction::{Press, Release}};
+use winapi::um::sysinfoapi::GetTickCount;
+use winapi::um::winuser::{
+    GetForegroundWindow, GetLastInputInfo, PeekMessageW, RegisterHotKey, SetForegroundWindow,
+    UnregisterHotKey, LASTINPUTINFO, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MSG, PM_REMOVE,
+    WM_HOTKEY,
+};
 
-use crate::config::{AutoControlMode, Config, CurrentHpState, CurrentState, MuteOptions};
-use crate::hp::HpBarFinder;
+use crate::command::ControlCommand;
+use crate::config::{
+    AudioIssue, AutoControlMode, ClickAnchor, ClickRestore, ClickTarget, Config, CurrentHpState, CurrentState,
+    Hotkey, HpHistory, HpMarkers, HpSample, InputAction, MuteOptions, SafetyStop, SoundEvent,
+};
+use crate::event_log::{EventLog, LogKind};
+use crate::food::FoodWatch;
+use crate::history::History;
+use crate::hp::{find_window, find_window_rect, HpBarFinder};
+use crate::jsonl_log::JsonlLog;
+use crate::metrics::Metrics;
+use crate::notifier::{AudioError, LowHpAlarm, Notifier};
+use crate::schedule::Scheduler;
+use crate::stats::SessionStats;
 
-pub struct Notifier{
-    low_hp_alert: &'static str,
-    high_hp_alert: &'static str,
-    volume: f32
-}
+/// How often the delay before an automated input checks whether auto mode was
+/// switched off in the meantime.
+const INPUT_DELAY_STEP: std::time::Duration = std::time::Duration::from_millis(50);
 
-impl Notifier {
-    pub fn new(volume: f32, low_hp_alert: &'static str, high_hp_alert: &'static str) -> Self {
-        Notifier{
-            low_hp_alert,
-            high_hp_alert,
-            volume
+/// Waits for `delay`, returning `false` as soon as auto mode is switched off or
+/// stopped by a safeguard, like the emergency stop hotkey.
+fn wait_while_auto(shared_app_state: &RwLock<CurrentState>, delay: std::time::Duration) -> bool {
+    let deadline = std::time::Instant::now() + delay;
+    loop {
+        let state = *shared_app_state.read().unwrap();
+        if state.auto_control == AutoControlMode::Off || state.safety_stop.is_some() {
+            return false;
         }
-    }
-
-    fn notify(&self, file: std::fs::File) -> Result<(), &str> {
-        let (_stream, handle) = rodio::OutputStream::try_default().expect(
-            "Failed to get default output stream"
-        );
-        let sink = rodio::Sink::try_new(&handle).unwrap();
-        sink.append(
-            rodio::Decoder::new(
-                std::io::BufReader::new(file)
-            ).expect("Failed to create decoder")
-        );
-
-        sink.set_volume(self.volume);
-        sink.sleep_until_end();
-        Ok(())
-    }
-
-    pub fn low_hp_notify(&mut self) -> Result<(), &str> {
-        self.notify(
-            std::fs::File::open(self.low_hp_alert).expect("Failed to open low hp file")
-        )
-    }
-
-    pub fn high_hp_notify(&mut self) -> Result<(), &str> {
-        self.notify(
-            std::fs::File::open(self.high_hp_alert).expect("Failed to open high hp file")
-        )
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        thread::sleep(remaining.min(INPUT_DELAY_STEP));
     }
 }
 
 pub struct AutoClicker{
-    enigo: Enigo
+    enigo: Enigo,
+    /// Tick count right after our last synthetic input.
+    own_input_tick: u32,
+    /// Tick count of the last input that came from the user, if any was seen.
+    user_input_tick: Option<u32>,
 }
 
 impl AutoClicker {
     pub fn new() -> Result<Self, &'static str> {
         Ok(AutoClicker{
-            enigo: Enigo::new(&Settings::default()).expect("Failed to create enigo")
+            enigo: Enigo::new(&Settings::default()).expect("Failed to create enigo"),
+            own_input_tick: unsafe { GetTickCount() },
+            user_input_tick: None,
         })
     }
 
-    pub fn click(&mut self, x: i32, y: i32, mouse_button: Button, sleep_duration: std::time::Duration) {
-        thread::sleep(sleep_duration);
+    /// Time since the user last moved the mouse or pressed a key, ignoring the
+    /// input we synthesized ourselves. `None` if no user input was seen yet.
+    pub fn user_idle_time(&mut self) -> Option<std::time::Duration> {
+        let mut info = LASTINPUTINFO {
+            cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+            dwTime: 0,
+        };
+        let now = unsafe {
+            GetLastInputInfo(&mut info);
+            GetTickCount()
+        };
+        if (info.dwTime.wrapping_sub(self.own_input_tick) as i32) > 0 {
+            self.user_input_tick = Some(info.dwTime);
+        }
+        self.user_input_tick
+            .map(|tick| std::time::Duration::from_millis(now.wrapping_sub(tick) as u64))
+    }
+
+    pub fn click(
+        &mut self,
+        x: i32,
+        y: i32,
+        mouse_button: Button,
+        restore: ClickRestore,
+    ) {
+        let cursor = if restore.cursor { self.enigo.location().ok() } else { None };
+        let window = if restore.focus { unsafe { GetForegroundWindow() } } else { null_mut() };
+
         self.enigo.move_mouse(x, y, Coordinate::Abs).unwrap();
         self.enigo.button(mouse_button, Press).unwrap();
         thread::sleep(std::time::Duration::from_millis(20));
         self.enigo.button(mouse_button, Release).unwrap();
+
+        if let Some((cursor_x, cursor_y)) = cursor {
+            self.enigo.move_mouse(cursor_x, cursor_y, Coordinate::Abs).unwrap();
+        }
+        if !window.is_null() {
+            unsafe { SetForegroundWindow(window) };
+        }
+        self.own_input_tick = unsafe { GetTickCount() };
+    }
+
+    /// Presses `chord` as a single shortcut. Returns `false` without pressing anything
+    /// if `window_name` is set but no such window exists.
+    pub fn press_keys(
+        &mut self,
+        chord: &[Key],
+        window_name: Option<&str>,
+        restore: ClickRestore,
+    ) -> bool {
+        let previous_window = if restore.focus { unsafe { GetForegroundWindow() } } else { null_mut() };
+
+        if let Some(window_name) = window_name {
+            let Ok(window_name) = CString::new(window_name) else {
+                return false;
+            };
+            let window = find_window(&window_name);
+            if window.is_null() {
+                return false;
+            }
+            unsafe { SetForegroundWindow(window) };
+            thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        for key in chord {
+            self.enigo.key(*key, Press).unwrap();
+        }
+        thread::sleep(std::time::Duration::from_millis(20));
+        for key in chord.iter().rev() {
+            self.enigo.key(*key, Release).unwrap();
+        }
+
+        if !previous_window.is_null() {
+            unsafe { SetForegroundWindow(previous_window) };
+        }
+        self.own_input_tick = unsafe { GetTickCount() };
+        true
     }
 }
 
@@ -77,56 +151,324 @@ pub struct AutoControl {
     hp_bar_finder: HpBarFinder,
     config: Config,
     shared_app_state: Arc<RwLock<CurrentState>>,
+    event_log: Arc<RwLock<EventLog>>,
+    hp_history: Arc<RwLock<HpHistory>>,
+    commands: mpsc::Receiver<ControlCommand>,
     app_state: CurrentState,
     high_hp_notified: bool,
+    low_hp_alarm: LowHpAlarm,
+    food_watch: FoodWatch,
+    scheduler: Scheduler,
+    /// Markers for the HP sample of the current tick.
+    markers: HpMarkers,
+    input_deferred: Option<std::time::Duration>,
+    recent_inputs: VecDeque<std::time::Instant>,
+    failed_toggles: u32,
+    /// Auto mode to switch back to once a `pause` command runs out.
+    paused_mode: AutoControlMode,
+    stats: SessionStats,
+    metrics: Metrics,
+    last_tick: std::time::Instant,
+    /// `None` if the history database couldn't be used.
+    history: Option<History>,
+    /// State as of the last recorded transitions.
+    recorded_state: CurrentState,
+    jsonl_log: Arc<RwLock<JsonlLog>>,
     tick_rate: std::time::Duration,
-    thieving_switch_button_coords: [i32; 2],
 }
 
 impl AutoControl {
     pub fn new(
         window_name: &'static str,
         shared_app_state: Arc<RwLock<CurrentState>>,
-        config: Config,
-        thieving_switch_button_coords: [i32; 2],
-        low_hp_alert: &'static str,
-        high_hp_alert: &'static str,
-        tick_rate: std::time::Duration
+        event_log: Arc<RwLock<EventLog>>,
+        hp_history: Arc<RwLock<HpHistory>>,
+        jsonl_log: Arc<RwLock<JsonlLog>>,
+        commands: mpsc::Receiver<ControlCommand>,
+        config: Config
     ) -> Result<Self, &'static str> {
+        let tick_rate = std::time::Duration::from_millis(config.tick_rate_ms);
         let auto_clicker = AutoClicker::new()?;
-        let notifier = Notifier::new(config.volume, low_hp_alert, high_hp_alert);
+        let notifier = Notifier::new(shared_app_state.clone(), event_log.clone(), &config);
+        let low_hp_alarm = LowHpAlarm::new(config.low_hp_escalation.clone());
+        let food_watch = FoodWatch::new(std::time::Duration::from_secs(config.food_out_secs));
+        let scheduler = Scheduler::new(config.schedule);
         let hp_bar_finder = HpBarFinder::new(window_name);
+        shared_app_state.write().unwrap().settings = config.live_settings();
         let app_state = *shared_app_state.read().unwrap();
+        event_log.write().unwrap().push(
+            LogKind::Config,
+            format!("Loaded config, signal threshold {}%", config.signal_threshold)
+        );
+        let history = History::start_session(&config.profile)
+            .map_err(|err| event_log.write().unwrap().push(
+                LogKind::Error,
+                format!("Session history disabled: {}", err)
+            ))
+            .ok();
 
-        Ok(AutoControl{
+        let auto_control = AutoControl{
             auto_clicker,
             notifier,
             hp_bar_finder,
             config,
             shared_app_state,
+            event_log,
+            hp_history,
+            commands,
             app_state,
             high_hp_notified: false,
+            low_hp_alarm,
+            food_watch,
+            scheduler,
+            markers: HpMarkers::default(),
+            input_deferred: None,
+            recent_inputs: VecDeque::new(),
+            failed_toggles: 0,
+            paused_mode: AutoControlMode::Off,
+            stats: SessionStats::default(),
+            metrics: Metrics::default(),
+            last_tick: std::time::Instant::now(),
+            history,
+            recorded_state: app_state,
+            jsonl_log,
             tick_rate,
-            thieving_switch_button_coords
-        })
+        };
+        auto_control.open_jsonl_log();
+        Ok(auto_control)
     }
 
-    pub fn stop_thieving(&mut self) {
-        if self.app_state.auto_control == AutoControlMode::Temporarily && self.app_state.is_thieving_active == true {
-            self.shared_app_state.write().unwrap().auto_control = AutoControlMode::Off;
+    /// Turns a configured click target into absolute screen coordinates, or `None`
+    /// if the window or HP bar it is anchored to can't be found right now.
+    fn resolve_click_target(&self, target: &ClickTarget) -> Option<[i32; 2]> {
+        let origin = match &target.anchor {
+            ClickAnchor::Screen => [0, 0],
+            ClickAnchor::Window(None) => self.hp_bar_finder.window_origin()?,
+            ClickAnchor::Window(Some(name)) => {
+                let rect = find_window_rect(&CString::new(name.as_str()).ok()?)?;
+                [rect.left, rect.top]
+            }
+            ClickAnchor::HpBar => self.hp_bar_finder.hp_bar_origin()?,
+        };
+        Some([origin[0] + target.x, origin[1] + target.y])
+    }
+
+    /// How much longer the user has to stay idle before we may send input.
+    fn idle_wait(&mut self) -> Option<std::time::Duration> {
+        let required = std::time::Duration::from_secs(self.config.user_idle_secs as u64);
+        if required.is_zero() {
+            return None;
+        }
+        match self.auto_clicker.user_idle_time() {
+            Some(idle) if idle < required => Some(required - idle),
+            _ => None,
+        }
+    }
+
+    fn log(&self, kind: LogKind, message: impl Into<String>) {
+        self.event_log.write().unwrap().push(kind, message);
+    }
+
+    /// Runs `record` against the history database, giving up on it after the first failure.
+    fn record_history(&mut self, record: impl FnOnce(&mut History) -> rusqlite::Result<()>) {
+        if let Some(history) = &mut self.history {
+            if let Err(err) = record(history) {
+                self.log(LogKind::Error, format!("Session history disabled: {}", err));
+                self.history = None;
+            }
+        }
+    }
+
+    /// Opens the JSONL log of the current config, closing the one before.
+    fn open_jsonl_log(&self) {
+        let path = self.config.jsonl_log.as_deref();
+        if let Err(err) = self.jsonl_log.write().unwrap().open(path) {
+            self.log(LogKind::Error, format!("Failed to open JSONL log {}: {}", path.unwrap_or_default(), err));
+        }
+    }
+
+    /// Runs `record` against the JSONL log, which closes itself after the first failure.
+    fn record_jsonl(&self, record: impl FnOnce(&mut JsonlLog) -> std::io::Result<()>) {
+        let result = record(&mut self.jsonl_log.write().unwrap());
+        if let Err(err) = result {
+            self.log(LogKind::Error, format!("JSONL log disabled: {}", err));
+        }
+    }
+
+    /// Values of the state fields whose changes go into the session history.
+    fn transition_values(state: &CurrentState) -> [(&'static str, String); 6] {
+        [
+            ("auto_control", state.auto_control.to_string()),
+            ("is_muted", match state.is_muted {
+                MuteOptions::TimedMute { minutes, .. } => format!("For {}m", minutes),
+                is_muted => is_muted.to_string(),
+            }),
+            ("is_thieving_active", state.is_thieving_active.to_string()),
+            ("window_found", state.on_top_replica_found.to_string()),
+            ("bar_found", (state.hp != CurrentHpState::BarNotFound).to_string()),
+            ("safety_stop", state.safety_stop.map_or("No".to_string(), |reason| reason.to_string())),
+        ]
+    }
+
+    fn record_transitions(&mut self) {
+        let state = *self.shared_app_state.read().unwrap();
+        let previous = Self::transition_values(&self.recorded_state);
+        for ((field, value), (_, previous)) in Self::transition_values(&state).iter().zip(previous.iter()) {
+            if value != previous {
+                self.record_history(|history| history.record_transition(field, value));
+            }
+        }
+        self.recorded_state = state;
+        self.record_jsonl(|jsonl_log| jsonl_log.record_state(state.snapshot()));
+    }
+
+    /// Marks the current tick as having fired an alert and reports a dead audio thread.
+    fn record_alert(&mut self, result: Result<(), AudioError>) {
+        self.markers.alert = true;
+        self.stats.record_alert();
+        self.check_audio(result);
+    }
+
+    /// Reports a dead audio thread.
+    fn check_audio(&mut self, result: Result<(), AudioError>) {
+        if let Err(err) = result {
+            if self.app_state.audio_issue != Some(AudioIssue::Stopped) {
+                self.log(LogKind::Error, err.to_string());
+            }
+            self.app_state.audio_issue = Some(AudioIssue::Stopped);
+            self.shared_app_state.write().unwrap().audio_issue = Some(AudioIssue::Stopped);
+        }
+    }
+
+    fn notify_event(&mut self, event: SoundEvent) {
+        if self.app_state.alerts_enabled() {
+            let result = self.notifier.event_notify(event);
+            // Routine events and ones switched off in the config aren't alerts.
+            let enabled = self.config.event_sounds.get(&event).is_some_and(|sound| sound.enabled);
+            if event.is_error() && enabled {
+                self.record_alert(result);
+            } else {
+                self.check_audio(result);
+            }
+        }
+    }
+
+    fn trip_safety_stop(&mut self, reason: SafetyStop) {
+        self.log(LogKind::Error, format!("Safety stop: {}", reason));
+        self.app_state.auto_control = AutoControlMode::Off;
+        let mut shared_app_state = self.shared_app_state.write().unwrap();
+        shared_app_state.auto_control = AutoControlMode::Off;
+        shared_app_state.safety_stop = Some(reason);
+    }
+
+    fn click_rate_exceeded(&mut self) -> bool {
+        let minute_ago = std::time::Instant::now() - std::time::Duration::from_secs(60);
+        while self.recent_inputs.front().is_some_and(|&time| time < minute_ago) {
+            self.recent_inputs.pop_front();
+        }
+        self.config.max_inputs_per_minute != 0 &&
+            self.recent_inputs.len() >= self.config.max_inputs_per_minute as usize
+    }
+
+    /// Sends the input of `action` and records it, returning what was done and
+    /// whether its target was found.
+    fn send_input(
+        &mut self,
+        action: &InputAction,
+        restore: ClickRestore
+    ) -> (String, bool) {
+        let (description, performed) = match action {
+            InputAction::Click(target) => match self.resolve_click_target(target) {
+                Some([x, y]) => {
+                    self.auto_clicker.click(x, y, Button::Left, restore);
+                    (format!("Clicked at ({}, {})", x, y), true)
+                }
+                None => ("Click".to_string(), false),
+            },
+            InputAction::Keys { chord, window } => {
+                let pressed = self.auto_clicker.press_keys(chord, window.as_deref(), restore);
+                (format!("Pressed {:?}", chord), pressed)
+            }
+        };
+        self.record_history(|history| history.record_action(&description, performed));
+        self.record_jsonl(|jsonl_log| jsonl_log.record_action(&description, performed));
+        if performed {
+            self.metrics.record_input();
+        }
+        (description, performed)
+    }
+
+    /// Defers input while the user is active, returning whether it did.
+    fn defer_for_user(&mut self) -> bool {
+        self.input_deferred = self.idle_wait();
+        if self.input_deferred.is_some() && self.app_state.input_deferred.is_none() {
+            self.log(LogKind::Action, "Input deferred, user is active");
+        }
+        self.input_deferred.is_some()
+    }
+
+    /// Performs `action` after `sleep_duration`, returning `false` if its target
+    /// couldn't be found, the user is still active or a safety limit tripped.
+    fn perform_action(
+        &mut self,
+        action: &InputAction,
+        sleep_duration: std::time::Duration,
+        restore: ClickRestore
+    ) -> bool {
+        if self.defer_for_user() {
+            return false;
+        }
+        if !wait_while_auto(&self.shared_app_state, sleep_duration) {
+            self.log(LogKind::Mode, "Automated input cancelled, auto mode was switched off");
+            return false;
+        }
+        // Checked again after the delay, so a user who became active during it
+        // keeps their cursor.
+        if !sleep_duration.is_zero() && self.defer_for_user() {
+            return false;
         }
+        if self.click_rate_exceeded() {
+            self.trip_safety_stop(SafetyStop::ClickRateLimit);
+            return false;
+        }
+        let (description, performed) = self.send_input(action, restore);
+        if performed {
+            self.log(LogKind::Action, description);
+            self.recent_inputs.push_back(std::time::Instant::now());
+            self.failed_toggles = 0;
+        } else {
+            self.log(LogKind::Error, "Automated input target not found");
+            self.notify_event(SoundEvent::ClickFailed);
+            self.failed_toggles += 1;
+            if self.config.max_failed_toggles != 0 && self.failed_toggles >= self.config.max_failed_toggles {
+                self.trip_safety_stop(SafetyStop::FailedToggles);
+            }
+        }
+        performed
+    }
+
+    pub fn stop_thieving(&mut self) {
         if (
             self.app_state.auto_control == AutoControlMode::Temporarily ||
                 self.app_state.auto_control == AutoControlMode::On
         ) && self.app_state.is_thieving_active == true {
-            self.auto_clicker.click(
-                self.thieving_switch_button_coords[0],
-                self.thieving_switch_button_coords[1],
-                Button::Left,
-                std::time::Duration::from_secs(3)
-            );
+            let action = self.config.stop_thieving_action.clone();
+            if !self.perform_action(
+                &action,
+                std::time::Duration::from_secs(3),
+                self.config.stop_thieving_restore
+            ) {
+                return;
+            }
+            if self.app_state.auto_control == AutoControlMode::Temporarily {
+                self.shared_app_state.write().unwrap().auto_control = AutoControlMode::Off;
+            }
             self.shared_app_state.write().unwrap().is_thieving_active = false;
             self.app_state.is_thieving_active = false;
+            self.markers.thieving_stopped = true;
+            self.stats.thieving_stopped();
+            self.log(LogKind::Action, "Thieving stopped");
+            self.notify_event(SoundEvent::ThievingStopped);
         }
 
     }
@@ -134,49 +476,362 @@ impl AutoControl {
     fn start_thieving(&mut self) {
         match self.app_state.auto_control {
             AutoControlMode::On | AutoControlMode::Temporarily if self.app_state.is_thieving_active == false => {
+                let action = self.config.start_thieving_action.clone();
+                if !self.perform_action(
+                    &action,
+                    std::time::Duration::default(),
+                    self.config.start_thieving_restore
+                ) {
+                    return;
+                }
                 self.shared_app_state.write().unwrap().is_thieving_active = true;
                 self.app_state.is_thieving_active = true;
-                self.auto_clicker.click(
-                    self.thieving_switch_button_coords[0],
-                    self.thieving_switch_button_coords[1],
-                    Button::Left,
-                    std::time::Duration::default()
-                );
+                self.markers.thieving_started = true;
+                self.stats.thieving_started();
+                self.log(LogKind::Action, "Thieving started");
+                self.notify_event(SoundEvent::ThievingStarted);
             }
             _ => {},
         };
     }
 
+    /// Performs the start thieving action once on request. The user just typed
+    /// the command, so the idle check and the safeguards don't apply, and the
+    /// thieving state is left alone.
+    fn click_test(&mut self) {
+        let action = self.config.start_thieving_action.clone();
+        let (description, performed) = self.send_input(&action, self.config.start_thieving_restore);
+        if performed {
+            self.log(LogKind::Action, format!("Click test: {}", description));
+            self.log(LogKind::Mode, "Thieving may have been toggled in the game, check the thieving state");
+        } else {
+            self.log(LogKind::Error, "Click test: target not found");
+        }
+    }
+
+    /// Swaps in a whole new config, restarting the parts built from the old one.
+    fn apply_config(&mut self, config: Config) {
+        // Dropping the old notifier lets its audio thread play what is still
+        // queued and exit on its own, without holding up the control loop.
+        self.notifier = Notifier::new(self.shared_app_state.clone(), self.event_log.clone(), &config);
+        self.low_hp_alarm = LowHpAlarm::new(config.low_hp_escalation.clone());
+        self.food_watch = FoodWatch::new(std::time::Duration::from_secs(config.food_out_secs));
+        self.scheduler = Scheduler::new(config.schedule);
+        self.hp_history.write().unwrap().set_window(config.hp_history_window());
+        self.config = config;
+        self.open_jsonl_log();
+    }
+
+    fn apply_command(&mut self, command: ControlCommand) {
+        match &command {
+            ControlCommand::SetThreshold(threshold) => self.config.signal_threshold = *threshold,
+            ControlCommand::SetVolume(volume) => {
+                self.config.volume = *volume;
+                let result = self.notifier.set_volume(*volume);
+                if let Err(err) = result {
+                    self.log(LogKind::Error, err.to_string());
+                }
+            }
+            ControlCommand::SetMinHp(min_hp) => {
+                self.config.min_hp = *min_hp;
+                self.config.update_signal_threshold();
+            }
+            ControlCommand::SetMaxHp(max_hp) => {
+                self.config.max_hp = *max_hp;
+                self.config.update_signal_threshold();
+            }
+            ControlCommand::SetTickRate(tick_rate) => {
+                self.tick_rate = *tick_rate;
+                self.config.tick_rate_ms = tick_rate.as_millis() as u64;
+            }
+            ControlCommand::SaveConfig => {
+                if let Err(err) = self.config.save_into_file() {
+                    self.log(LogKind::Error, format!("Failed to save profile {}: {}", self.config.profile, err));
+                    return;
+                }
+            }
+            ControlCommand::ClickTest => self.click_test(),
+            ControlCommand::LoadProfile(name) => match Config::load_profile(name) {
+                Ok(config) => {
+                    self.tick_rate = std::time::Duration::from_millis(config.tick_rate_ms);
+                    self.record_history(History::end_session);
+                    self.history = History::start_session(&config.profile)
+                        .map_err(|err| self.log(LogKind::Error, format!("Session history disabled: {}", err)))
+                        .ok();
+                    self.apply_config(config);
+                }
+                Err(err) => {
+                    self.log(LogKind::Error, format!("Failed to load profile {}: {}", name, err));
+                    return;
+                }
+            },
+            ControlCommand::Adjust(field, steps) => {
+                let command = field.adjust(&self.config.live_settings(), *steps);
+                self.apply_command(command);
+                return;
+            }
+            ControlCommand::Pause(duration) => {
+                if self.app_state.paused_until.is_none() {
+                    self.paused_mode = self.app_state.auto_control;
+                }
+                let until = std::time::Instant::now() + *duration;
+                self.app_state.auto_control = AutoControlMode::Off;
+                self.app_state.paused_until = Some(until);
+                let mut shared_app_state = self.shared_app_state.write().unwrap();
+                shared_app_state.auto_control = AutoControlMode::Off;
+                shared_app_state.paused_until = Some(until);
+            }
+        }
+        self.shared_app_state.write().unwrap().settings = self.config.live_settings();
+        self.log(LogKind::Config, format!("Applied \"{}\"", command));
+    }
+
+    /// Ends a `pause` once it runs out, or as soon as auto mode is switched on by hand.
+    fn update_pause(&mut self) {
+        let Some(until) = self.app_state.paused_until else {
+            return;
+        };
+        if self.app_state.auto_control != AutoControlMode::Off {
+            self.app_state.paused_until = None;
+        } else if std::time::Instant::now() >= until {
+            self.log(LogKind::Mode, format!("Pause is over, auto mode: {}", self.paused_mode));
+            self.app_state.paused_until = None;
+            self.app_state.auto_control = self.paused_mode;
+            self.shared_app_state.write().unwrap().auto_control = self.paused_mode;
+        } else {
+            return;
+        }
+        self.shared_app_state.write().unwrap().paused_until = None;
+    }
+
+    /// Sleeps until the next tick, applying commands as soon as they arrive.
+    fn wait_for_commands(&mut self) {
+        let next_tick = std::time::Instant::now() + self.tick_rate;
+        let timeout = || next_tick.saturating_duration_since(std::time::Instant::now());
+        while let Ok(command) = self.commands.recv_timeout(timeout()) {
+            self.app_state.update_from(&self.shared_app_state.read().unwrap());
+            self.apply_command(command);
+            self.record_transitions();
+        }
+    }
+
     pub fn run(&mut self) {
         while self.app_state.is_running {
-            let mut sleep_duration = self.tick_rate;
+            self.input_deferred = None;
+            self.markers = HpMarkers::default();
             self.app_state.update_from(&self.shared_app_state.read().unwrap());
+            self.update_pause();
+            if let MuteOptions::TimedMute { until, .. } = self.app_state.is_muted {
+                if std::time::Instant::now() >= until {
+                    self.log(LogKind::Mode, "Timed mute expired");
+                    self.app_state.is_muted = MuteOptions::Unmute;
+                    self.shared_app_state.write().unwrap().is_muted = MuteOptions::Unmute;
+                }
+            }
+            let (schedule, auto_off) = self.scheduler.tick();
+            self.app_state.schedule = schedule;
+            if auto_off {
+                self.log(LogKind::Mode, "Auto mode switched off by schedule");
+                self.app_state.auto_control = AutoControlMode::Off;
+                self.shared_app_state.write().unwrap().auto_control = AutoControlMode::Off;
+            }
+            let detection_start = std::time::Instant::now();
             let current_hp = self.hp_bar_finder.get_hp();
+            self.metrics.record_detection(detection_start.elapsed(), self.hp_bar_finder.capture_duration());
+            let window_found = self.hp_bar_finder.window_was_found();
+            if self.app_state.on_top_replica_found && !window_found {
+                self.log(LogKind::Detection, "Window lost");
+                self.notify_event(SoundEvent::WindowLost);
+            } else if matches!(self.app_state.hp, CurrentHpState::Hp(_)) &&
+                current_hp == CurrentHpState::BarNotFound
+            {
+                self.log(LogKind::Detection, "HP bar lost");
+                self.notify_event(SoundEvent::BarLost);
+            } else if !self.app_state.on_top_replica_found && window_found {
+                self.log(LogKind::Detection, "Window found");
+            }
 
             if let CurrentHpState::Hp(hp) = &current_hp {
                 if *hp >= 99.0 {
                     self.start_thieving();
                     if self.high_hp_notified == false {
                         self.high_hp_notified = true;
-                        self.notifier.high_hp_notify().unwrap();
+                        self.log(LogKind::Alert, "HP recovered");
+                        // Quiet hours and mutes silence the recovery chirp like every other alert.
+                        if self.app_state.alerts_enabled() {
+                            let result = self.notifier.high_hp_notify();
+                            self.record_alert(result);
+                        }
                     }
                 }
                 if *hp < self.config.signal_threshold as f32 {
                     self.stop_thieving();
                     self.high_hp_notified = false;
-                    if self.app_state.is_muted == MuteOptions::Unmute {
-                        self.notifier.low_hp_notify().unwrap();
-                        sleep_duration = std::time::Duration::from_millis(3000);
+                    if self.app_state.alerts_enabled() {
+                        if let Some(step) = self.low_hp_alarm.tick() {
+                            if step.first {
+                                self.log(LogKind::Alert, format!("Low HP: {:.2}%", hp));
+                            }
+                            let result = self.notifier.low_hp_notify(&step);
+                            self.record_alert(result);
+                            if step.run_hook {
+                                self.log(LogKind::Alert, "Running low HP hook");
+                                if let Err(err) = self.notifier.run_hook(&self.config.low_hp_escalation.hook_command) {
+                                    self.log(LogKind::Error, format!("Low HP hook failed: {}", err));
+                                }
+                            }
+                        }
                     }
+                } else {
+                    self.low_hp_alarm.reset();
                 }
                 if *hp < self.config.signal_threshold as f32 &&
                     self.app_state.is_muted == MuteOptions::TempMute
                 {
+                    self.log(LogKind::Mode, "Temporary mute ended");
                     self.shared_app_state.write().unwrap().is_muted = MuteOptions::Unmute;
                 }
             };
-            self.shared_app_state.write().unwrap().hp = current_hp;
-            thread::sleep(sleep_duration);
+            if self.food_watch.tick(current_hp, self.config.signal_threshold as f32) {
+                self.log(LogKind::Alert, "Food seems to have run out, HP isn't going up");
+                self.notify_event(SoundEvent::FoodOut);
+            }
+            let now = std::time::Instant::now();
+            self.stats.record_tick(now - self.last_tick, current_hp, self.app_state.is_thieving_active);
+            self.last_tick = now;
+            {
+                let mut shared_app_state = self.shared_app_state.write().unwrap();
+                shared_app_state.stats = self.stats;
+                shared_app_state.metrics = self.metrics;
+                shared_app_state.hp = current_hp;
+                shared_app_state.on_top_replica_found = window_found;
+                shared_app_state.input_deferred = self.input_deferred;
+                shared_app_state.schedule = schedule;
+            }
+            self.hp_history.write().unwrap().push(HpSample {
+                time: std::time::Instant::now(),
+                hp: current_hp,
+                markers: self.markers,
+            });
+            self.record_history(|history| history.record_sample(current_hp));
+            self.record_transitions();
+            self.wait_for_commands();
         }
+        self.notify_event(SoundEvent::SessionEnd);
+        self.notifier.finish();
+        self.record_history(History::end_session);
     }
-}
\ No newline at end of file
+}
+
+
+/// Watches a global hotkey that switches automation off even when the terminal
+/// isn't focused.
+pub struct EmergencyStop {
+    shared_app_state: Arc<RwLock<CurrentState>>,
+    event_log: Arc<RwLock<EventLog>>,
+    hotkey: Hotkey,
+    tick_rate: std::time::Duration,
+}
+
+impl EmergencyStop {
+    const HOTKEY_ID: i32 = 1;
+
+    pub fn new(
+        shared_app_state: Arc<RwLock<CurrentState>>,
+        event_log: Arc<RwLock<EventLog>>,
+        hotkey: Hotkey,
+        tick_rate: std::time::Duration
+    ) -> Self {
+        EmergencyStop {
+            shared_app_state,
+            event_log,
+            hotkey,
+            tick_rate,
+        }
+    }
+
+    fn modifiers(&self) -> u32 {
+        let mut modifiers = MOD_NOREPEAT;
+        if self.hotkey.ctrl {
+            modifiers |= MOD_CONTROL;
+        }
+        if self.hotkey.alt {
+            modifiers |= MOD_ALT;
+        }
+        if self.hotkey.shift {
+            modifiers |= MOD_SHIFT;
+        }
+        modifiers as u32
+    }
+
+    pub fn run(&mut self) {
+        // The hotkey is bound to this thread's message queue, so it has to be
+        // registered and polled from the same thread.
+        let registered = unsafe {
+            RegisterHotKey(null_mut(), Self::HOTKEY_ID, self.modifiers(), self.hotkey.key)
+        };
+        if registered == 0 {
+            self.event_log.write().unwrap().push(LogKind::Error, "Failed to register emergency stop hotkey");
+            return;
+        }
+
+        let mut msg: MSG = unsafe { std::mem::zeroed() };
+        while self.shared_app_state.read().unwrap().is_running {
+            while unsafe { PeekMessageW(&mut msg, null_mut(), WM_HOTKEY, WM_HOTKEY, PM_REMOVE) } != 0 {
+                let mut shared_app_state = self.shared_app_state.write().unwrap();
+                shared_app_state.auto_control = AutoControlMode::Off;
+                shared_app_state.safety_stop = Some(SafetyStop::EmergencyStop);
+                self.event_log.write().unwrap().push(LogKind::Error, "Safety stop: Emergency stop hotkey");
+            }
+            thread::sleep(self.tick_rate);
+        }
+
+        unsafe { UnregisterHotKey(null_mut(), Self::HOTKEY_ID) };
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn auto_on() -> Arc<RwLock<CurrentState>> {
+        Arc::new(RwLock::new(CurrentState { auto_control: AutoControlMode::On, ..CurrentState::default() }))
+    }
+
+    #[test]
+    fn delay_runs_out_while_auto_stays_on() {
+        let shared_app_state = auto_on();
+        let start = Instant::now();
+        assert!(wait_while_auto(&shared_app_state, Duration::from_millis(120)));
+        assert!(start.elapsed() >= Duration::from_millis(120));
+    }
+
+    #[test]
+    fn emergency_stop_during_the_delay_cancels_the_input() {
+        let shared_app_state = auto_on();
+        let stopper = thread::spawn({
+            let shared_app_state = shared_app_state.clone();
+            move || {
+                thread::sleep(Duration::from_millis(100));
+                let mut state = shared_app_state.write().unwrap();
+                state.auto_control = AutoControlMode::Off;
+                state.safety_stop = Some(SafetyStop::EmergencyStop);
+            }
+        });
+
+        let start = Instant::now();
+        assert!(!wait_while_auto(&shared_app_state, Duration::from_secs(3)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        stopper.join().unwrap();
+    }
+
+    #[test]
+    fn switched_off_auto_cancels_even_without_a_delay() {
+        let shared_app_state = auto_on();
+        shared_app_state.write().unwrap().toggle_auto();
+        assert!(!wait_while_auto(&shared_app_state, Duration::ZERO));
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::settings::SettingField;
+
+/// A runtime change from the command line or settings editor of the terminal
+/// UI, applied by `AutoControl` as soon as it arrives.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ControlCommand {
+    /// Low-HP threshold in percent.
+    SetThreshold(u32),
+    SetVolume(f32),
+    /// Also recomputes the threshold from it, like the startup prompts do.
+    SetMinHp(u32),
+    /// Also recomputes the threshold from it, like the startup prompts do.
+    SetMaxHp(u32),
+    SetTickRate(Duration),
+    /// Writes the config as it is now to the active profile.
+    SaveConfig,
+    /// Performs the start thieving action once, to check that it hits its target.
+    ClickTest,
+    /// Switches to the config saved as `<name>_screenserver.json`.
+    LoadProfile(String),
+    /// Switches auto mode off for a while, then back to what it was.
+    Pause(Duration),
+    /// Moves a setting `steps` steps from the value it has when the command is
+    /// applied, so key repeats of the settings editor add up even while
+    /// `AutoControl` is busy.
+    Adjust(SettingField, i32),
+}
+
+impl ControlCommand {
+    pub const USAGE: &'static str = "threshold <0-100>, volume <0.0-1.0>, min_hp <n>, max_hp <n>, tick <ms>, save, \
+        click test, profile <name>, pause <10m|30s|1h>";
+}
+
+/// Parses `10m`, `30s`, `1h` or a bare number of minutes.
+fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, unit_secs) = match text.char_indices().last()? {
+        (i, 's') => (&text[..i], 1),
+        (i, 'm') => (&text[..i], 60),
+        (i, 'h') => (&text[..i], 60 * 60),
+        _ => (text, 60),
+    };
+    let number: u64 = number.parse().ok()?;
+    number.checked_mul(unit_secs).map(Duration::from_secs)
+}
+
+impl FromStr for ControlCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let invalid = || format!("Unknown command \"{}\", expected one of: {}", line.trim(), Self::USAGE);
+        match words.as_slice() {
+            ["threshold", value] => value.parse().ok()
+                .filter(|threshold| *threshold <= 100)
+                .map(ControlCommand::SetThreshold)
+                .ok_or_else(|| format!("Threshold must be a percentage, got \"{}\"", value)),
+            ["volume", value] => value.parse().ok()
+                .filter(|volume| (0.0..=1.0).contains(volume))
+                .map(ControlCommand::SetVolume)
+                .ok_or_else(|| format!("Volume must be between 0.0 and 1.0, got \"{}\"", value)),
+            ["min_hp", value] => value.parse()
+                .map(ControlCommand::SetMinHp)
+                .map_err(|_| format!("Min HP must be a whole number, got \"{}\"", value)),
+            ["max_hp", value] => value.parse().ok()
+                .filter(|max_hp| *max_hp > 0)
+                .map(ControlCommand::SetMaxHp)
+                .ok_or_else(|| format!("Max HP must be a positive whole number, got \"{}\"", value)),
+            ["tick", value] => value.trim_end_matches("ms").parse().ok()
+                .filter(|millis| *millis >= 100)
+                .map(|millis| ControlCommand::SetTickRate(Duration::from_millis(millis)))
+                .ok_or_else(|| format!("Tick rate must be at least 100ms, got \"{}\"", value)),
+            ["save"] => Ok(ControlCommand::SaveConfig),
+            ["click", "test"] => Ok(ControlCommand::ClickTest),
+            // The name becomes part of a file name, so it must not reach out of the working directory.
+            ["profile", name] if name.contains(['/', '\\', ':']) || name.contains("..") => {
+                Err(format!("Profile names can't contain paths, got \"{}\"", name))
+            }
+            ["profile", name] => Ok(ControlCommand::LoadProfile(name.to_string())),
+            ["pause", duration] => parse_duration(duration)
+                .filter(|duration| !duration.is_zero())
+                .map(ControlCommand::Pause)
+                .ok_or_else(|| format!("Pause needs a duration like 10m, got \"{}\"", duration)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for ControlCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControlCommand::SetThreshold(threshold) => write!(f, "threshold {}", threshold),
+            ControlCommand::SetVolume(volume) => write!(f, "volume {}", volume),
+            ControlCommand::SetMinHp(min_hp) => write!(f, "min_hp {}", min_hp),
+            ControlCommand::SetMaxHp(max_hp) => write!(f, "max_hp {}", max_hp),
+            ControlCommand::SetTickRate(tick_rate) => write!(f, "tick {}ms", tick_rate.as_millis()),
+            ControlCommand::SaveConfig => write!(f, "save"),
+            ControlCommand::ClickTest => write!(f, "click test"),
+            ControlCommand::LoadProfile(name) => write!(f, "profile {}", name),
+            ControlCommand::Pause(duration) => write!(f, "pause {}s", duration.as_secs()),
+            ControlCommand::Adjust(field, steps) => write!(f, "{} {:+} steps", field.name(), steps),
+        }
+    }
+}
+
+
+/// Text typed into the `:` command line, shared between the key handler and
+/// the UI. `None` while the command line is closed.
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    input: Option<String>,
+}
+
+impl CommandLine {
+    pub fn open(&mut self) {
+        self.input = Some(String::new());
+    }
+
+    pub fn close(&mut self) {
+        self.input = None;
+    }
+
+    pub fn input(&self) -> Option<&str> {
+        self.input.as_deref()
+    }
+
+    pub fn push(&mut self, c: char) {
+        if let Some(input) = &mut self.input {
+            input.push(c);
+        }
+    }
+
+    pub fn pop(&mut self) {
+        if let Some(input) = &mut self.input {
+            input.pop();
+        }
+    }
+
+    /// Closes the command line, returning what was typed.
+    pub fn submit(&mut self) -> Option<String> {
+        self.input.take()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<ControlCommand, String> {
+        line.parse()
+    }
+
+    #[test]
+    fn parses_every_command() {
+        assert_eq!(parse("threshold 40"), Ok(ControlCommand::SetThreshold(40)));
+        assert_eq!(parse("volume 0.5"), Ok(ControlCommand::SetVolume(0.5)));
+        assert_eq!(parse("min_hp 300"), Ok(ControlCommand::SetMinHp(300)));
+        assert_eq!(parse("max_hp 1200"), Ok(ControlCommand::SetMaxHp(1200)));
+        assert_eq!(parse("tick 250ms"), Ok(ControlCommand::SetTickRate(Duration::from_millis(250))));
+        assert_eq!(parse("tick 250"), Ok(ControlCommand::SetTickRate(Duration::from_millis(250))));
+        assert_eq!(parse("save"), Ok(ControlCommand::SaveConfig));
+        assert_eq!(parse("  click   test "), Ok(ControlCommand::ClickTest));
+        assert_eq!(parse("profile farm"), Ok(ControlCommand::LoadProfile("farm".to_string())));
+    }
+
+    #[test]
+    fn parses_pause_durations() {
+        assert_eq!(parse("pause 30s"), Ok(ControlCommand::Pause(Duration::from_secs(30))));
+        assert_eq!(parse("pause 10m"), Ok(ControlCommand::Pause(Duration::from_secs(600))));
+        assert_eq!(parse("pause 10"), Ok(ControlCommand::Pause(Duration::from_secs(600))));
+        assert_eq!(parse("pause 1h"), Ok(ControlCommand::Pause(Duration::from_secs(3600))));
+        for invalid in ["pause 0m", "pause m", "pause -5m", "pause 10d", "pause 99999999999999999h"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        for invalid in ["threshold 101", "volume 1.5", "volume -0.1", "max_hp 0", "tick 50ms", "min_hp -1"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn rejects_profile_names_with_paths() {
+        for invalid in ["profile ../x", "profile ..", "profile a/b", "profile a\\b", "profile C:x"] {
+            let err = parse(invalid).unwrap_err();
+            assert!(err.starts_with("Profile names"), "{}: {}", invalid, err);
+        }
+        assert_eq!(parse("profile farm.v2"), Ok(ControlCommand::LoadProfile("farm.v2".to_string())));
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        for invalid in ["", "mute", "threshold", "threshold 40 50", "click"] {
+            let err = parse(invalid).unwrap_err();
+            assert!(err.starts_with("Unknown command"), "{}: {}", invalid, err);
+        }
+    }
+
+    #[test]
+    fn displays_as_it_parses() {
+        for line in ["threshold 40", "volume 0.5", "tick 250ms", "click test", "profile farm", "pause 600s"] {
+            assert_eq!(parse(line).unwrap().to_string(), line);
+        }
+    }
+
+    #[test]
+    fn command_line_collects_input_while_open() {
+        let mut command_line = CommandLine::default();
+        command_line.push('x');
+        assert_eq!(command_line.input(), None);
+
+        command_line.open();
+        for c in "savee".chars() {
+            command_line.push(c);
+        }
+        command_line.pop();
+        assert_eq!(command_line.input(), Some("save"));
+        assert_eq!(command_line.submit(), Some("save".to_string()));
+        assert_eq!(command_line.input(), None);
+    }
+}
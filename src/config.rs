@@ -1,28 +1,138 @@
+use chrono::NaiveTime;
+use enigo::Key;
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::{fmt::Display, fs::File, io::{Read, Write}};
+use std::{collections::{HashMap, VecDeque}, fmt::Display, fs::File, io::{self, Read, Write}};
 
+use crate::metrics::Metrics;
+use crate::stats::SessionStats;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub max_hp: u32,
     pub min_hp: u32,
     pub volume: f32,
     pub signal_threshold: u32,
+    #[serde(default)]
+    pub start_thieving_restore: ClickRestore,
+    #[serde(default)]
+    pub stop_thieving_restore: ClickRestore,
+    #[serde(default = "InputAction::thieving_switch_button")]
+    pub start_thieving_action: InputAction,
+    #[serde(default = "InputAction::thieving_switch_button")]
+    pub stop_thieving_action: InputAction,
+    /// How long the user must not touch mouse or keyboard before an automated
+    /// input is allowed. `0` disables the check.
+    #[serde(default)]
+    pub user_idle_secs: u32,
+    /// `0` disables the limit.
+    #[serde(default)]
+    pub max_inputs_per_minute: u32,
+    /// Automated inputs in a row whose target couldn't be found. `0` disables the limit.
+    #[serde(default)]
+    pub max_failed_toggles: u32,
+    /// Seconds HP may stay below the threshold without going up from eating
+    /// before the food counts as run out. `0` disables the check.
+    #[serde(default = "Config::default_food_out_secs")]
+    pub food_out_secs: u64,
+    #[serde(default)]
+    pub emergency_stop_hotkey: Hotkey,
+    #[serde(default = "AlertSound::low_hp")]
+    pub low_hp_sound: AlertSound,
+    #[serde(default = "AlertSound::high_hp")]
+    pub high_hp_sound: AlertSound,
+    #[serde(default)]
+    pub low_hp_escalation: LowHpEscalation,
+    /// Events missing from the map stay silent.
+    #[serde(default = "EventSound::defaults")]
+    pub event_sounds: HashMap<SoundEvent, EventSound>,
+    #[serde(default)]
+    pub alert_output: AlertOutput,
+    #[serde(default)]
+    pub schedule: Schedule,
+    #[serde(default = "KeyBinding::defaults")]
+    pub keymap: Vec<KeyBinding>,
+    /// How often HP is checked.
+    #[serde(default = "Config::default_tick_rate_ms")]
+    pub tick_rate_ms: u64,
+    /// How far back the HP chart goes.
+    #[serde(default = "Config::default_hp_history_minutes")]
+    pub hp_history_minutes: u64,
+    /// Appends state changes and automated actions to this file as JSON lines.
+    #[serde(default)]
+    pub jsonl_log: Option<String>,
+    /// Serves the HTTP API on 127.0.0.1 at this port when set. Prometheus
+    /// metrics are served from its `/metrics` endpoint, so there are none
+    /// without it.
+    #[serde(default)]
+    pub http_api_port: Option<u16>,
+    /// Streams HP samples and state changes over WebSocket on 127.0.0.1 at
+    /// this port when set.
+    #[serde(default)]
+    pub live_stream_port: Option<u16>,
+    /// Name of the profile the config was loaded from.
+    #[serde(skip)]
+    pub profile: String,
 }
 
 impl Config {
-    pub fn save_into_file(&self) {
-        let config_json = serde_json::to_string(&self).expect("Failed to serialize JSON");
-        let mut file = File::create("default_screenserver.json").expect("Failed to create file");
-        file.write_all(config_json.as_bytes()).expect("Failed to write to file");
+    fn default_tick_rate_ms() -> u64 {
+        1000
+    }
+
+    fn default_hp_history_minutes() -> u64 {
+        10
+    }
+
+    fn default_food_out_secs() -> u64 {
+        120
+    }
+
+    pub fn hp_history_window(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.hp_history_minutes * 60)
+    }
+
+    /// Derives `signal_threshold` from `min_hp` as a share of `max_hp`.
+    pub fn update_signal_threshold(&mut self) {
+        if let Some(threshold) = (self.min_hp as u64 * 100).checked_div(self.max_hp as u64) {
+            self.signal_threshold = threshold.min(100) as u32;
+        }
+    }
+
+    pub fn live_settings(&self) -> LiveSettings {
+        LiveSettings {
+            volume: self.volume,
+            signal_threshold: self.signal_threshold,
+            min_hp: self.min_hp,
+            max_hp: self.max_hp,
+            tick_rate: std::time::Duration::from_millis(self.tick_rate_ms),
+        }
+    }
+
+    fn profile_path(name: &str) -> String {
+        format!("{}_screenserver.json", name)
+    }
+
+    /// Writes the config to the file of the profile it was loaded from.
+    pub fn save_into_file(&self) -> io::Result<()> {
+        let config_json = serde_json::to_string(&self)?;
+        let mut file = File::create(Self::profile_path(&self.profile))?;
+        file.write_all(config_json.as_bytes())
     }
 
     pub fn load_from_file() -> Result<Self, String> {
-        let mut file = File::open("default_screenserver.json").map_err(|e| e.to_string())?;
+        Self::load_profile("default")
+    }
+
+    /// Loads `<name>_screenserver.json`; the prompted config is the `default` profile.
+    pub fn load_profile(name: &str) -> Result<Self, String> {
+        let path = Self::profile_path(name);
+        let mut file = File::open(&path).map_err(|e| format!("{}: {}", path, e))?;
         let mut contents = String::new();
         file.read_to_string(&mut contents).map_err(|e| e.to_string())?;
-        let config: Config = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        let mut config: Config = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        config.profile = name.to_string();
         Ok(config)
     }
 }
@@ -37,12 +147,197 @@ impl Default for Config {
                 min_hp: 0,
                 volume: 1.0,
                 signal_threshold: 0,
+                start_thieving_restore: ClickRestore::default(),
+                stop_thieving_restore: ClickRestore::default(),
+                start_thieving_action: InputAction::thieving_switch_button(),
+                stop_thieving_action: InputAction::thieving_switch_button(),
+                user_idle_secs: 0,
+                max_inputs_per_minute: 0,
+                max_failed_toggles: 0,
+                food_out_secs: Config::default_food_out_secs(),
+                emergency_stop_hotkey: Hotkey::default(),
+                low_hp_sound: AlertSound::low_hp(),
+                high_hp_sound: AlertSound::high_hp(),
+                low_hp_escalation: LowHpEscalation::default(),
+                event_sounds: EventSound::defaults(),
+                alert_output: AlertOutput::default(),
+                schedule: Schedule::default(),
+                keymap: KeyBinding::defaults(),
+                tick_rate_ms: Config::default_tick_rate_ms(),
+                hp_history_minutes: Config::default_hp_history_minutes(),
+                jsonl_log: None,
+                http_api_port: None,
+                live_stream_port: None,
+                profile: "default".to_string(),
             }
         }
     }
 }
 
 
+/// What to put back after an automated click or key press: the cursor position
+/// and/or the window that had focus before it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct ClickRestore {
+    pub cursor: bool,
+    pub focus: bool,
+}
+
+impl Default for ClickRestore {
+    fn default() -> Self {
+        ClickRestore {
+            cursor: true,
+            focus: true,
+        }
+    }
+}
+
+
+/// What the coordinates of a `ClickTarget` are measured from.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub enum ClickAnchor {
+    Screen,
+    /// Top-left corner of the window with the given title, or of the watched window if `None`.
+    Window(Option<String>),
+    /// First pixel of the detected HP bar.
+    HpBar,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct ClickTarget {
+    pub anchor: ClickAnchor,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// A single automated input issued by `AutoControl`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub enum InputAction {
+    Click(ClickTarget),
+    /// Keys pressed together in order and released in reverse, after bringing
+    /// the window with the given title to the foreground if one is set.
+    Keys {
+        chord: Vec<Key>,
+        window: Option<String>,
+    },
+}
+
+impl InputAction {
+    fn thieving_switch_button() -> Self {
+        InputAction::Click(ClickTarget {
+            anchor: ClickAnchor::Screen,
+            x: 820,
+            y: 790,
+        })
+    }
+}
+
+
+/// A system-wide shortcut: modifiers plus a Windows virtual-key code.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub key: u32,
+}
+
+impl Default for Hotkey {
+    /// The Pause key on its own.
+    fn default() -> Self {
+        Hotkey {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            key: 0x13,
+        }
+    }
+}
+
+
+/// Something the terminal UI does on a key press.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum KeyCommand {
+    ToggleMute,
+    ToggleTempMute,
+    CycleTimedMute,
+    ToggleAuto,
+    ToggleTempAuto,
+    ToggleThieving,
+    ScrollUp,
+    ScrollDown,
+    PageUp,
+    PageDown,
+    ScrollOldest,
+    ScrollNewest,
+    OpenCommandLine,
+    ToggleSettings,
+    Quit,
+}
+
+impl Display for KeyCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyCommand::ToggleMute => write!(f, "Mute on/off"),
+            KeyCommand::ToggleTempMute => write!(f, "Temporarily mute on/off"),
+            KeyCommand::CycleTimedMute => write!(f, "Mute for 5/15/30 minutes"),
+            KeyCommand::ToggleAuto => write!(f, "Auto mode on/off"),
+            KeyCommand::ToggleTempAuto => write!(f, "Temporarily auto mode on/off"),
+            KeyCommand::ToggleThieving => write!(f, "Thieving on/off"),
+            KeyCommand::ScrollUp => write!(f, "Scroll events up"),
+            KeyCommand::ScrollDown => write!(f, "Scroll events down"),
+            KeyCommand::PageUp => write!(f, "Scroll events a page up"),
+            KeyCommand::PageDown => write!(f, "Scroll events a page down"),
+            KeyCommand::ScrollOldest => write!(f, "Oldest event"),
+            KeyCommand::ScrollNewest => write!(f, "Newest event"),
+            KeyCommand::OpenCommandLine => write!(f, "Command line"),
+            KeyCommand::ToggleSettings => write!(f, "Settings editor"),
+            KeyCommand::Quit => write!(f, "Quit"),
+        }
+    }
+}
+
+/// A key or chord such as `"M"`, `"Esc"` or `"Ctrl+C"` and the command it runs.
+/// Letters and digits match the physical key, whatever the keyboard layout.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct KeyBinding {
+    pub keys: String,
+    pub command: KeyCommand,
+}
+
+impl KeyBinding {
+    fn new(keys: &str, command: KeyCommand) -> Self {
+        KeyBinding {
+            keys: keys.to_string(),
+            command,
+        }
+    }
+
+    pub fn defaults() -> Vec<KeyBinding> {
+        vec![
+            KeyBinding::new("M", KeyCommand::ToggleMute),
+            KeyBinding::new("Esc", KeyCommand::ToggleTempMute),
+            KeyBinding::new("T", KeyCommand::ToggleTempMute),
+            KeyBinding::new("D", KeyCommand::CycleTimedMute),
+            KeyBinding::new("A", KeyCommand::ToggleAuto),
+            KeyBinding::new("S", KeyCommand::ToggleTempAuto),
+            KeyBinding::new("B", KeyCommand::ToggleThieving),
+            KeyBinding::new("Up", KeyCommand::ScrollUp),
+            KeyBinding::new("Down", KeyCommand::ScrollDown),
+            KeyBinding::new("PageUp", KeyCommand::PageUp),
+            KeyBinding::new("PageDown", KeyCommand::PageDown),
+            KeyBinding::new("Home", KeyCommand::ScrollOldest),
+            KeyBinding::new("End", KeyCommand::ScrollNewest),
+            KeyBinding::new(":", KeyCommand::OpenCommandLine),
+            KeyBinding::new("E", KeyCommand::ToggleSettings),
+            KeyBinding::new("Q", KeyCommand::Quit),
+            // Raw mode swallows the Ctrl-C signal, so it arrives as a key press.
+            KeyBinding::new("Ctrl+C", KeyCommand::Quit),
+        ]
+    }
+}
+
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum CurrentHpState {
     Hp(f32),
@@ -59,12 +354,28 @@ impl Default for CurrentHpState {
 pub enum MuteOptions {
     Mute,
     TempMute,
+    /// Muted for `minutes`, until `until` passes.
+    TimedMute { until: std::time::Instant, minutes: u64 },
     #[default]
     Unmute,
 }
 
+impl Display for MuteOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MuteOptions::Mute => write!(f, "Yes"),
+            MuteOptions::TempMute => write!(f, "Temporarily"),
+            MuteOptions::TimedMute { until, .. } => {
+                let left = until.saturating_duration_since(std::time::Instant::now()).as_secs();
+                write!(f, "For {}:{:02}", left / 60, left % 60)
+            }
+            MuteOptions::Unmute => write!(f, "No"),
+        }
+    }
+}
 
-#[derive(Debug, PartialEq, Default, Clone, Copy)]
+
+#[derive(Debug, Serialize, PartialEq, Default, Clone, Copy)]
 pub enum AutoControlMode {
     On,
     #[default]
@@ -72,6 +383,267 @@ pub enum AutoControlMode {
     Temporarily
 }
 
+/// A synthesized alert: `repeats` beeps separated by `gap_ms` of silence, each
+/// sweeping linearly from `start_frequency` to `end_frequency` Hz.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct Tone {
+    pub start_frequency: f32,
+    pub end_frequency: f32,
+    pub beep_ms: u32,
+    pub gap_ms: u32,
+    pub repeats: u32,
+}
+
+impl Tone {
+    fn new(start_frequency: f32, end_frequency: f32, beep_ms: u32, gap_ms: u32, repeats: u32) -> Self {
+        Tone {
+            start_frequency,
+            end_frequency,
+            beep_ms,
+            gap_ms,
+            repeats,
+        }
+    }
+
+    /// Urgent alert for low HP.
+    pub fn triple_beep() -> Self {
+        Tone {
+            start_frequency: 1000.0,
+            end_frequency: 1000.0,
+            beep_ms: 120,
+            gap_ms: 80,
+            repeats: 3,
+        }
+    }
+
+    /// Alert for HP that has stayed low for a while.
+    pub fn rapid_beep() -> Self {
+        Tone {
+            start_frequency: 1400.0,
+            end_frequency: 1400.0,
+            beep_ms: 70,
+            gap_ms: 40,
+            repeats: 5,
+        }
+    }
+
+    /// Gentle alert for recovered HP.
+    pub fn rising_chirp() -> Self {
+        Tone {
+            start_frequency: 600.0,
+            end_frequency: 1200.0,
+            beep_ms: 250,
+            gap_ms: 0,
+            repeats: 1,
+        }
+    }
+}
+
+/// Where an alert's sound comes from. Sound files that can't be played are
+/// replaced by the alert's built-in tone.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub enum AlertSound {
+    File(String),
+    Tone(Tone),
+}
+
+impl AlertSound {
+    fn low_hp() -> Self {
+        AlertSound::Tone(Tone::triple_beep())
+    }
+
+    fn high_hp() -> Self {
+        AlertSound::Tone(Tone::rising_chirp())
+    }
+}
+
+/// How the low-HP alert escalates while HP stays below `signal_threshold`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct LowHpEscalation {
+    /// Volume of the first alert, relative to `Config::volume`.
+    pub initial_volume: f32,
+    /// Added to the relative volume on every repeat, up to full volume.
+    pub volume_step: f32,
+    /// Switch to `urgent_sound` once HP has been low this long. `0` disables.
+    pub urgent_after_secs: u32,
+    pub urgent_sound: AlertSound,
+    /// Run `hook_command` once HP has been low this long. `0` disables.
+    pub hook_after_secs: u32,
+    pub hook_command: String,
+}
+
+impl Default for LowHpEscalation {
+    fn default() -> Self {
+        LowHpEscalation {
+            initial_volume: 0.6,
+            volume_step: 0.1,
+            urgent_after_secs: 15,
+            urgent_sound: AlertSound::Tone(Tone::rapid_beep()),
+            hook_after_secs: 0,
+            hook_command: String::new(),
+        }
+    }
+}
+
+/// Where alerts go: speakers, desktop notifications or both.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy, Default)]
+pub enum AlertOutput {
+    #[default]
+    Audio,
+    Desktop,
+    Both,
+}
+
+/// Things besides low and recovered HP that can play a sound.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum SoundEvent {
+    BarLost,
+    WindowLost,
+    ThievingStopped,
+    ThievingStarted,
+    ClickFailed,
+    FoodOut,
+    SessionEnd,
+}
+
+impl SoundEvent {
+    pub const ALL: [SoundEvent; 7] = [
+        SoundEvent::BarLost,
+        SoundEvent::WindowLost,
+        SoundEvent::ThievingStopped,
+        SoundEvent::ThievingStarted,
+        SoundEvent::ClickFailed,
+        SoundEvent::FoodOut,
+        SoundEvent::SessionEnd,
+    ];
+
+    /// Whether the event reports something going wrong, as opposed to routine
+    /// progress like thieving starting or the session ending.
+    pub fn is_error(self) -> bool {
+        matches!(self, SoundEvent::BarLost | SoundEvent::WindowLost | SoundEvent::ClickFailed | SoundEvent::FoodOut)
+    }
+
+    /// Built-in tone for the event, also used when its sound file can't be played.
+    pub fn default_tone(self) -> Tone {
+        match self {
+            SoundEvent::BarLost => Tone::new(500.0, 500.0, 150, 100, 2),
+            SoundEvent::WindowLost => Tone::new(400.0, 400.0, 200, 100, 2),
+            SoundEvent::ThievingStopped => Tone::new(900.0, 500.0, 200, 0, 1),
+            SoundEvent::ThievingStarted => Tone::new(500.0, 900.0, 200, 0, 1),
+            SoundEvent::ClickFailed => Tone::new(300.0, 300.0, 300, 0, 1),
+            SoundEvent::FoodOut => Tone::new(700.0, 700.0, 100, 60, 4),
+            SoundEvent::SessionEnd => Tone::new(800.0, 400.0, 400, 0, 1),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct EventSound {
+    pub enabled: bool,
+    pub sound: AlertSound,
+    /// Relative to `Config::volume`.
+    pub volume: f32,
+}
+
+impl EventSound {
+    fn defaults() -> HashMap<SoundEvent, EventSound> {
+        SoundEvent::ALL.into_iter().map(|event| {
+            // Thieving toggles already come with the low/high HP alerts.
+            let enabled = !matches!(event, SoundEvent::ThievingStopped | SoundEvent::ThievingStarted);
+            (event, EventSound {
+                enabled,
+                sound: AlertSound::Tone(event.default_tone()),
+                volume: 1.0,
+            })
+        }).collect()
+    }
+}
+
+/// A span of local time of day; `end` before `start` wraps past midnight.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct TimeRange {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeRange {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy, Default)]
+#[serde(default)]
+pub struct Schedule {
+    /// Alerts are muted during this time, e.g. 23:00 to 08:00.
+    pub quiet_hours: Option<TimeRange>,
+    /// Auto mode is switched off when this time of day passes.
+    pub auto_off_at: Option<NaiveTime>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Clone, Copy, Default)]
+pub struct ScheduleState {
+    /// End of the quiet hours currently in effect.
+    pub quiet_until: Option<NaiveTime>,
+    pub auto_off_at: Option<NaiveTime>,
+}
+
+impl Display for ScheduleState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.quiet_until, self.auto_off_at) {
+            (None, None) => write!(f, "None"),
+            (Some(end), None) => write!(f, "Quiet hours until {}", end.format("%H:%M")),
+            (None, Some(at)) => write!(f, "Auto off at {}", at.format("%H:%M")),
+            (Some(end), Some(at)) => write!(
+                f, "Quiet hours until {}, auto off at {}", end.format("%H:%M"), at.format("%H:%M")
+            ),
+        }
+    }
+}
+
+/// Ways audio alerts are degraded, ordered from least to most severe.
+#[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum AudioIssue {
+    FallbackDevice,
+    MissingSound,
+    TerminalBell,
+    Stopped,
+}
+
+impl Display for AudioIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioIssue::FallbackDevice => write!(f, "Default device unavailable, using another one"),
+            AudioIssue::MissingSound => write!(f, "Sound file missing, using built-in tone"),
+            AudioIssue::TerminalBell => write!(f, "No audio device, using terminal bell"),
+            AudioIssue::Stopped => write!(f, "Audio stopped"),
+        }
+    }
+}
+
+/// Why a safeguard switched `AutoControlMode` to Off.
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+pub enum SafetyStop {
+    ClickRateLimit,
+    FailedToggles,
+    EmergencyStop,
+}
+
+impl Display for SafetyStop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SafetyStop::ClickRateLimit => write!(f, "Too many clicks per minute"),
+            SafetyStop::FailedToggles => write!(f, "Too many failed toggles"),
+            SafetyStop::EmergencyStop => write!(f, "Emergency stop hotkey"),
+        }
+    }
+}
+
 impl Display for AutoControlMode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -83,6 +655,74 @@ impl Display for AutoControlMode {
 }
 
 
+/// What happened on the tick an HP sample was taken.
+#[derive(Debug, Serialize, PartialEq, Clone, Copy, Default)]
+pub struct HpMarkers {
+    pub thieving_stopped: bool,
+    pub thieving_started: bool,
+    pub alert: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HpSample {
+    pub time: std::time::Instant,
+    pub hp: CurrentHpState,
+    pub markers: HpMarkers,
+}
+
+/// HP samples of the last few minutes, oldest first. Shared on its own rather
+/// than as part of `CurrentState`, so copying the state stays cheap.
+#[derive(Debug)]
+pub struct HpHistory {
+    samples: VecDeque<HpSample>,
+    window: std::time::Duration,
+}
+
+impl HpHistory {
+    pub fn new(window: std::time::Duration) -> Self {
+        HpHistory {
+            samples: VecDeque::new(),
+            window,
+        }
+    }
+
+    /// Adds `sample`, dropping the samples that are now older than the window.
+    pub fn push(&mut self, sample: HpSample) {
+        self.samples.push_back(sample);
+        self.trim(sample.time);
+    }
+
+    pub fn set_window(&mut self, window: std::time::Duration) {
+        self.window = window;
+        if let Some(newest) = self.samples.back() {
+            self.trim(newest.time);
+        }
+    }
+
+    fn trim(&mut self, now: std::time::Instant) {
+        while self.samples.front().is_some_and(|sample| now - sample.time > self.window) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Samples from oldest to newest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &HpSample> {
+        self.samples.iter()
+    }
+}
+
+
+/// The config values that can be changed while running, as `AutoControl` currently uses them.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct LiveSettings {
+    pub volume: f32,
+    pub signal_threshold: u32,
+    pub min_hp: u32,
+    pub max_hp: u32,
+    pub tick_rate: std::time::Duration,
+}
+
+
 #[derive(Debug, Clone, Copy)]
 pub struct CurrentState {
     pub hp: CurrentHpState,
@@ -90,6 +730,18 @@ pub struct CurrentState {
     pub is_muted: MuteOptions,
     pub auto_control: AutoControlMode,
     pub is_thieving_active: bool,
+    /// Time left until a pending automated input may fire, while the user is active.
+    pub input_deferred: Option<std::time::Duration>,
+    pub safety_stop: Option<SafetyStop>,
+    pub audio_issue: Option<AudioIssue>,
+    /// Whether the last desktop notification couldn't be shown.
+    pub desktop_notifications_failed: bool,
+    pub schedule: ScheduleState,
+    /// When a `pause` from the command line switches auto mode back on.
+    pub paused_until: Option<std::time::Instant>,
+    pub settings: LiveSettings,
+    pub stats: SessionStats,
+    pub metrics: Metrics,
     pub is_running: bool,
 }
 
@@ -101,6 +753,15 @@ impl Default for CurrentState {
             is_muted: MuteOptions::default(),
             auto_control: AutoControlMode::default(),
             is_thieving_active: false,
+            input_deferred: None,
+            safety_stop: None,
+            audio_issue: None,
+            desktop_notifications_failed: false,
+            schedule: ScheduleState::default(),
+            paused_until: None,
+            settings: LiveSettings::default(),
+            stats: SessionStats::default(),
+            metrics: Metrics::default(),
             is_running: true,
         }
     }
@@ -114,6 +775,15 @@ impl From <&CurrentState> for CurrentState {
             is_muted: other.is_muted,
             auto_control: other.auto_control,
             is_thieving_active: other.is_thieving_active,
+            input_deferred: other.input_deferred,
+            safety_stop: other.safety_stop,
+            audio_issue: other.audio_issue,
+            desktop_notifications_failed: other.desktop_notifications_failed,
+            schedule: other.schedule,
+            paused_until: other.paused_until,
+            settings: other.settings,
+            stats: other.stats,
+            metrics: other.metrics,
             is_running: other.is_running,
         }
     }
@@ -126,7 +796,274 @@ impl CurrentState {
         self.is_muted = other.is_muted;
         self.auto_control = other.auto_control;
         self.is_thieving_active = other.is_thieving_active;
+        self.input_deferred = other.input_deferred;
+        self.safety_stop = other.safety_stop;
+        self.audio_issue = other.audio_issue;
+        self.desktop_notifications_failed = other.desktop_notifications_failed;
+        self.schedule = other.schedule;
+        self.paused_until = other.paused_until;
+        self.settings = other.settings;
+        self.stats = other.stats;
+        self.metrics = other.metrics;
         self.is_running = other.is_running;
     }
+
+    /// Whether alerts may play: not muted by hand or by quiet hours.
+    pub fn alerts_enabled(&self) -> bool {
+        self.is_muted == MuteOptions::Unmute && self.schedule.quiet_until.is_none()
+    }
+
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            hp: match self.hp {
+                CurrentHpState::Hp(hp) => Some(hp),
+                CurrentHpState::BarNotFound => None,
+            },
+            window_found: self.on_top_replica_found,
+            muted: match self.is_muted {
+                MuteOptions::Mute => "Mute",
+                MuteOptions::TempMute => "TempMute",
+                MuteOptions::TimedMute { .. } => "TimedMute",
+                MuteOptions::Unmute => "Unmute",
+            },
+            timed_mute_minutes: match self.is_muted {
+                MuteOptions::TimedMute { minutes, .. } => Some(minutes),
+                _ => None,
+            },
+            auto_control: self.auto_control,
+            is_thieving_active: self.is_thieving_active,
+            input_deferred: self.input_deferred.is_some(),
+            safety_stop: self.safety_stop,
+            audio_issue: self.audio_issue,
+            desktop_notifications_failed: self.desktop_notifications_failed,
+            schedule: self.schedule,
+            paused: self.paused_until.is_some(),
+            is_running: self.is_running,
+        }
+    }
 }
 
+/// Durations the timed mute cycles through before unmuting again.
+const TIMED_MUTE_MINUTES: [u64; 3] = [5, 15, 30];
+
+/// Toggles triggered by the user, shared by the keyboard and the HTTP API.
+impl CurrentState {
+    pub fn toggle_mute(&mut self) {
+        self.is_muted = match self.is_muted {
+            MuteOptions::Mute | MuteOptions::TimedMute { .. } => MuteOptions::Unmute,
+            MuteOptions::TempMute | MuteOptions::Unmute => MuteOptions::Mute,
+        };
+    }
+
+    pub fn toggle_temp_mute(&mut self) {
+        self.is_muted = match self.is_muted {
+            MuteOptions::TempMute => MuteOptions::Unmute,
+            MuteOptions::Mute | MuteOptions::TimedMute { .. } | MuteOptions::Unmute => MuteOptions::TempMute,
+        };
+    }
+
+    /// Mutes for the next longer duration, unmuting after the longest one.
+    pub fn cycle_timed_mute(&mut self) {
+        let next_minutes = match self.is_muted {
+            MuteOptions::TimedMute { minutes, .. } => TIMED_MUTE_MINUTES.iter().find(|&&m| m > minutes),
+            _ => TIMED_MUTE_MINUTES.first(),
+        };
+        self.is_muted = match next_minutes {
+            Some(&minutes) => MuteOptions::TimedMute {
+                until: std::time::Instant::now() + std::time::Duration::from_secs(minutes * 60),
+                minutes,
+            },
+            None => MuteOptions::Unmute,
+        };
+    }
+
+    /// Switches auto mode back on, clearing the reason it was last stopped by a safeguard.
+    fn resume_auto_control(&mut self, mode: AutoControlMode) {
+        self.auto_control = mode;
+        self.safety_stop = None;
+    }
+
+    pub fn toggle_auto(&mut self) {
+        match self.auto_control {
+            AutoControlMode::Off => self.resume_auto_control(AutoControlMode::On),
+            AutoControlMode::On => self.auto_control = AutoControlMode::Off,
+            AutoControlMode::Temporarily => self.auto_control = AutoControlMode::On,
+        }
+    }
+
+    pub fn toggle_temp_auto(&mut self) {
+        match self.auto_control {
+            AutoControlMode::Off => self.resume_auto_control(AutoControlMode::Temporarily),
+            AutoControlMode::On => self.auto_control = AutoControlMode::Temporarily,
+            AutoControlMode::Temporarily => self.auto_control = AutoControlMode::Off,
+        }
+    }
+
+    pub fn toggle_thieving(&mut self) {
+        self.is_thieving_active = !self.is_thieving_active;
+    }
+}
+
+/// Serializable view of `CurrentState`, leaving out the countdowns that change
+/// on every tick.
+#[derive(Debug, Serialize, PartialEq, Clone)]
+pub struct StateSnapshot {
+    /// `None` while the HP bar isn't found.
+    pub hp: Option<f32>,
+    pub window_found: bool,
+    pub muted: &'static str,
+    pub timed_mute_minutes: Option<u64>,
+    pub auto_control: AutoControlMode,
+    pub is_thieving_active: bool,
+    pub input_deferred: bool,
+    pub safety_stop: Option<SafetyStop>,
+    pub audio_issue: Option<AudioIssue>,
+    pub desktop_notifications_failed: bool,
+    pub schedule: ScheduleState,
+    pub paused: bool,
+    pub is_running: bool,
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn sample(time: Instant) -> HpSample {
+        HpSample {
+            time,
+            hp: CurrentHpState::Hp(100.0),
+            markers: HpMarkers::default(),
+        }
+    }
+
+    /// Threshold derived from `min_hp` and `max_hp`, starting from 50%.
+    fn threshold(min_hp: u32, max_hp: u32) -> u32 {
+        let mut config = Config { min_hp, max_hp, signal_threshold: 50, ..Config::default() };
+        config.update_signal_threshold();
+        config.signal_threshold
+    }
+
+    #[test]
+    fn signal_threshold_is_min_hp_as_a_share_of_max_hp() {
+        assert_eq!(threshold(300, 1200), 25);
+        assert_eq!(threshold(u32::MAX, u32::MAX), 100);
+        assert_eq!(threshold(u32::MAX, 1), 100);
+        // Without a max HP the threshold is left alone.
+        assert_eq!(threshold(300, 0), 50);
+    }
+
+    #[test]
+    fn hp_history_keeps_only_its_window() {
+        let start = Instant::now();
+        let mut history = HpHistory::new(Duration::from_secs(60));
+        for secs in [0, 30, 60, 90] {
+            history.push(sample(start + Duration::from_secs(secs)));
+        }
+
+        let kept: Vec<_> = history.iter().map(|sample| (sample.time - start).as_secs()).collect();
+        assert_eq!(kept, [30, 60, 90]);
+
+        history.set_window(Duration::from_secs(30));
+        let kept: Vec<_> = history.iter().map(|sample| (sample.time - start).as_secs()).collect();
+        assert_eq!(kept, [60, 90]);
+    }
+
+    fn timed_mute_minutes(state: &CurrentState) -> Option<u64> {
+        match state.is_muted {
+            MuteOptions::TimedMute { minutes, .. } => Some(minutes),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn mute_toggles_switch_between_mute_kinds() {
+        let mut state = CurrentState::default();
+        state.toggle_mute();
+        assert_eq!(state.is_muted, MuteOptions::Mute);
+        state.toggle_temp_mute();
+        assert_eq!(state.is_muted, MuteOptions::TempMute);
+        state.toggle_mute();
+        assert_eq!(state.is_muted, MuteOptions::Mute);
+        state.toggle_mute();
+        assert_eq!(state.is_muted, MuteOptions::Unmute);
+        state.toggle_temp_mute();
+        state.toggle_temp_mute();
+        assert_eq!(state.is_muted, MuteOptions::Unmute);
+    }
+
+    #[test]
+    fn timed_mute_cycles_through_durations_then_unmutes() {
+        let mut state = CurrentState::default();
+        let mut cycle = Vec::new();
+        for _ in 0..4 {
+            state.cycle_timed_mute();
+            cycle.push(timed_mute_minutes(&state));
+        }
+        assert_eq!(cycle, [Some(5), Some(15), Some(30), None]);
+        assert_eq!(state.is_muted, MuteOptions::Unmute);
+
+        state.cycle_timed_mute();
+        state.toggle_mute();
+        assert_eq!(state.is_muted, MuteOptions::Unmute);
+    }
+
+    #[test]
+    fn auto_toggles_cycle_modes() {
+        let mut state = CurrentState::default();
+        state.toggle_auto();
+        assert_eq!(state.auto_control, AutoControlMode::On);
+        state.toggle_temp_auto();
+        assert_eq!(state.auto_control, AutoControlMode::Temporarily);
+        state.toggle_auto();
+        assert_eq!(state.auto_control, AutoControlMode::On);
+        state.toggle_auto();
+        assert_eq!(state.auto_control, AutoControlMode::Off);
+        state.toggle_temp_auto();
+        state.toggle_temp_auto();
+        assert_eq!(state.auto_control, AutoControlMode::Off);
+    }
+
+    #[test]
+    fn switching_auto_on_clears_the_safety_stop() {
+        let mut state = CurrentState { safety_stop: Some(SafetyStop::FailedToggles), ..CurrentState::default() };
+        state.toggle_auto();
+        assert_eq!(state.safety_stop, None);
+
+        state.toggle_auto();
+        state.safety_stop = Some(SafetyStop::ClickRateLimit);
+        state.toggle_temp_auto();
+        assert_eq!(state.auto_control, AutoControlMode::Temporarily);
+        assert_eq!(state.safety_stop, None);
+    }
+
+    #[test]
+    fn alerts_are_off_during_quiet_hours_and_mutes() {
+        let mut state = CurrentState::default();
+        assert!(state.alerts_enabled());
+
+        state.schedule.quiet_until = NaiveTime::from_hms_opt(8, 0, 0);
+        assert!(!state.alerts_enabled());
+        state.schedule.quiet_until = None;
+
+        state.toggle_mute();
+        assert!(!state.alerts_enabled());
+        state.toggle_temp_mute();
+        assert!(!state.alerts_enabled());
+        state.cycle_timed_mute();
+        assert!(!state.alerts_enabled());
+    }
+
+    #[test]
+    fn thieving_toggle_flips_the_flag() {
+        let mut state = CurrentState::default();
+        let before = state.is_thieving_active;
+        state.toggle_thieving();
+        assert_eq!(state.is_thieving_active, !before);
+        state.toggle_thieving();
+        assert_eq!(state.is_thieving_active, before);
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+use chrono::{DateTime, Local};
+
+use crate::config::CurrentState;
+
+/// Entries kept before the oldest ones are dropped.
+const EVENT_LOG_CAPACITY: usize = 1000;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LogKind {
+    Action,
+    Alert,
+    Mode,
+    Detection,
+    Config,
+    Error,
+}
+
+impl Display for LogKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogKind::Action => write!(f, "Action"),
+            LogKind::Alert => write!(f, "Alert"),
+            LogKind::Mode => write!(f, "Mode"),
+            LogKind::Detection => write!(f, "Detection"),
+            LogKind::Config => write!(f, "Config"),
+            LogKind::Error => write!(f, "Error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: DateTime<Local>,
+    pub kind: LogKind,
+    pub message: String,
+}
+
+/// Timestamped record of what the automation and the user did, shown in the
+/// terminal UI. Also keeps the UI's scroll position, which is driven by
+/// `KeyboardKeyPressProcessor`.
+#[derive(Debug, Default)]
+pub struct EventLog {
+    entries: VecDeque<LogEntry>,
+    /// How many entries the view is scrolled back from the newest one.
+    scroll: usize,
+}
+
+impl EventLog {
+    pub fn push(&mut self, kind: LogKind, message: impl Into<String>) {
+        if self.entries.len() == EVENT_LOG_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(LogEntry {
+            time: Local::now(),
+            kind,
+            message: message.into(),
+        });
+        // Keep a scrolled-back view on the same entries.
+        if self.scroll > 0 {
+            self.scroll = (self.scroll + 1).min(self.entries.len() - 1);
+        }
+    }
+
+    /// Logs the changes a user command made to the state.
+    pub fn push_changes(&mut self, before: &CurrentState, after: &CurrentState) {
+        if after.is_muted != before.is_muted {
+            self.push(LogKind::Mode, format!("Muted: {}", after.is_muted));
+        }
+        if after.auto_control != before.auto_control {
+            self.push(LogKind::Mode, format!("Auto mode: {}", after.auto_control));
+        }
+        if after.is_thieving_active != before.is_thieving_active {
+            self.push(LogKind::Mode, format!("Thieving marked {}", match after.is_thieving_active {
+                true => "active",
+                false => "inactive",
+            }));
+        }
+        if !after.is_running && before.is_running {
+            self.push(LogKind::Mode, "Quit requested");
+        }
+    }
+
+    /// Entries from oldest to newest.
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &LogEntry> + ExactSizeIterator {
+        self.entries.iter()
+    }
+
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    /// Scrolls back towards older entries for positive `lines`, forward otherwise.
+    pub fn scroll_by(&mut self, lines: isize) {
+        let max = self.entries.len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(lines).min(max);
+    }
+
+    pub fn scroll_to_oldest(&mut self) {
+        self.scroll = self.entries.len().saturating_sub(1);
+    }
+
+    pub fn scroll_to_newest(&mut self) {
+        self.scroll = 0;
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+use crate::config::CurrentHpState;
+
+/// Smallest rise of HP between two ticks, in percent, taken as eating rather
+/// than regeneration.
+const EATING_JUMP: f32 = 5.0;
+
+
+/// Tells when the food seems to have run out: HP stays below the low-HP
+/// threshold for `timeout` without ever jumping up the way eating makes it.
+pub struct FoodWatch {
+    /// `Duration::ZERO` disables the watch.
+    timeout: Duration,
+    /// When HP went low, or when food was last eaten since.
+    low_since: Option<Instant>,
+    last_hp: Option<f32>,
+    reported: bool,
+}
+
+impl FoodWatch {
+    pub fn new(timeout: Duration) -> Self {
+        FoodWatch {
+            timeout,
+            low_since: None,
+            last_hp: None,
+            reported: false,
+        }
+    }
+
+    /// Takes the HP of the current tick, returning `true` once when the food ran out.
+    pub fn tick(&mut self, hp: CurrentHpState, threshold: f32) -> bool {
+        self.tick_at(hp, threshold, Instant::now())
+    }
+
+    fn tick_at(&mut self, hp: CurrentHpState, threshold: f32, now: Instant) -> bool {
+        // Without the bar nothing is known, the watch resumes once it's back.
+        let CurrentHpState::Hp(hp) = hp else {
+            return false;
+        };
+        let ate = self.last_hp.is_some_and(|last_hp| hp - last_hp >= EATING_JUMP);
+        self.last_hp = Some(hp);
+        if hp >= threshold {
+            self.low_since = None;
+            self.reported = false;
+            return false;
+        }
+        if ate || self.low_since.is_none() {
+            self.low_since = Some(now);
+            self.reported = false;
+        }
+        let ran_out = !self.timeout.is_zero() &&
+            !self.reported &&
+            self.low_since.is_some_and(|since| now - since >= self.timeout);
+        self.reported |= ran_out;
+        ran_out
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `hps` one second apart, returning the seconds at which the food ran out.
+    fn run(watch: &mut FoodWatch, hps: &[Option<f32>]) -> Vec<usize> {
+        let start = Instant::now();
+        hps.iter().enumerate()
+            .filter(|&(secs, hp)| {
+                let hp = hp.map_or(CurrentHpState::BarNotFound, CurrentHpState::Hp);
+                watch.tick_at(hp, 50.0, start + Duration::from_secs(secs as u64))
+            })
+            .map(|(secs, _)| secs)
+            .collect()
+    }
+
+    #[test]
+    fn fires_once_when_low_hp_never_jumps_up() {
+        let mut watch = FoodWatch::new(Duration::from_secs(3));
+        let hps = [Some(60.0), Some(40.0), Some(38.0), Some(39.0), Some(36.0), Some(35.0), Some(34.0)];
+        assert_eq!(run(&mut watch, &hps), [4]);
+    }
+
+    #[test]
+    fn eating_restarts_the_timeout() {
+        let mut watch = FoodWatch::new(Duration::from_secs(3));
+        let hps = [Some(40.0), Some(38.0), Some(45.0), Some(43.0), Some(42.0), Some(41.0)];
+        assert_eq!(run(&mut watch, &hps), [5]);
+    }
+
+    #[test]
+    fn recovering_above_the_threshold_rearms_it() {
+        let mut watch = FoodWatch::new(Duration::from_secs(1));
+        let hps = [Some(40.0), Some(39.0), Some(38.0), Some(80.0), Some(40.0), Some(39.0)];
+        assert_eq!(run(&mut watch, &hps), [1, 5]);
+    }
+
+    #[test]
+    fn missing_bar_is_skipped() {
+        let mut watch = FoodWatch::new(Duration::from_secs(2));
+        let hps = [Some(40.0), None, None, Some(39.0)];
+        assert_eq!(run(&mut watch, &hps), [3]);
+    }
+
+    #[test]
+    fn zero_timeout_disables_it() {
+        let mut watch = FoodWatch::new(Duration::ZERO);
+        assert!(run(&mut watch, &[Some(40.0); 10]).is_empty());
+    }
+}
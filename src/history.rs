@@ -0,0 +1,213 @@
+use std::io::Write;
+
+use chrono::Local;
+use rusqlite::{params, Connection};
+
+use crate::config::CurrentHpState;
+
+
+/// Database every session is recorded into, next to the config files.
+pub const HISTORY_DB: &str = "session_history.sqlite3";
+
+/// HP samples are written this many at a time, in one transaction, rather
+/// than committing to disk on every tick.
+const SAMPLE_BATCH: usize = 30;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS sessions (
+        id INTEGER PRIMARY KEY,
+        profile TEXT NOT NULL,
+        started TEXT NOT NULL,
+        ended TEXT
+    );
+    CREATE TABLE IF NOT EXISTS samples (
+        session_id INTEGER NOT NULL REFERENCES sessions(id),
+        time TEXT NOT NULL,
+        hp REAL
+    );
+    CREATE TABLE IF NOT EXISTS transitions (
+        session_id INTEGER NOT NULL REFERENCES sessions(id),
+        time TEXT NOT NULL,
+        field TEXT NOT NULL,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS actions (
+        session_id INTEGER NOT NULL REFERENCES sessions(id),
+        time TEXT NOT NULL,
+        action TEXT NOT NULL,
+        performed INTEGER NOT NULL
+    );
+";
+
+fn open() -> rusqlite::Result<Connection> {
+    let connection = Connection::open(HISTORY_DB)?;
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    // In WAL mode this only syncs on checkpoints, a crash can lose the last
+    // transactions but never corrupts the database.
+    connection.pragma_update(None, "synchronous", "NORMAL")?;
+    connection.execute_batch(SCHEMA)?;
+    Ok(connection)
+}
+
+fn now() -> String {
+    Local::now().to_rfc3339()
+}
+
+/// Quotes a CSV field if it needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+
+#[derive(Debug)]
+pub struct SessionInfo {
+    pub id: i64,
+    pub profile: String,
+    pub started: String,
+    /// `None` if the session is still running or didn't end cleanly.
+    pub ended: Option<String>,
+    pub samples: i64,
+}
+
+/// Records HP samples, state transitions and automated actions of one session
+/// into `session_history.sqlite3`.
+pub struct History {
+    connection: Connection,
+    session_id: i64,
+    /// Time and HP of the samples not written yet.
+    pending_samples: Vec<(String, Option<f32>)>,
+}
+
+impl History {
+    pub fn start_session(profile: &str) -> rusqlite::Result<Self> {
+        let connection = open()?;
+        connection.execute(
+            "INSERT INTO sessions (profile, started) VALUES (?1, ?2)",
+            params![profile, now()],
+        )?;
+        let session_id = connection.last_insert_rowid();
+        Ok(History {
+            connection,
+            session_id,
+            pending_samples: Vec::with_capacity(SAMPLE_BATCH),
+        })
+    }
+
+    pub fn record_sample(&mut self, hp: CurrentHpState) -> rusqlite::Result<()> {
+        let hp = match hp {
+            CurrentHpState::Hp(hp) => Some(hp),
+            CurrentHpState::BarNotFound => None,
+        };
+        self.pending_samples.push((now(), hp));
+        if self.pending_samples.len() >= SAMPLE_BATCH {
+            self.write_samples()?;
+        }
+        Ok(())
+    }
+
+    fn write_samples(&mut self) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO samples (session_id, time, hp) VALUES (?1, ?2, ?3)",
+            )?;
+            for (time, hp) in &self.pending_samples {
+                statement.execute(params![self.session_id, time, hp])?;
+            }
+        }
+        transaction.commit()?;
+        self.pending_samples.clear();
+        Ok(())
+    }
+
+    pub fn record_transition(&self, field: &str, value: &str) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "INSERT INTO transitions (session_id, time, field, value) VALUES (?1, ?2, ?3, ?4)",
+            params![self.session_id, now(), field, value],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_action(&self, action: &str, performed: bool) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "INSERT INTO actions (session_id, time, action, performed) VALUES (?1, ?2, ?3, ?4)",
+            params![self.session_id, now(), action, performed],
+        )?;
+        Ok(())
+    }
+
+    pub fn end_session(&mut self) -> rusqlite::Result<()> {
+        self.write_samples()?;
+        self.connection.execute(
+            "UPDATE sessions SET ended = ?1 WHERE id = ?2",
+            params![now(), self.session_id],
+        )?;
+        Ok(())
+    }
+
+    /// All recorded sessions, oldest first.
+    pub fn sessions() -> rusqlite::Result<Vec<SessionInfo>> {
+        let connection = open()?;
+        let mut statement = connection.prepare(
+            "SELECT id, profile, started, ended,
+                (SELECT COUNT(*) FROM samples WHERE samples.session_id = sessions.id)
+            FROM sessions ORDER BY id",
+        )?;
+        let sessions = statement.query_map([], |row| Ok(SessionInfo {
+            id: row.get(0)?,
+            profile: row.get(1)?,
+            started: row.get(2)?,
+            ended: row.get(3)?,
+            samples: row.get(4)?,
+        }))?;
+        sessions.collect()
+    }
+
+    /// Writes everything recorded for a session as CSV, ordered by time.
+    /// Returns `false` if there is no such session.
+    pub fn export_csv(session_id: i64, output: &mut impl Write) -> Result<bool, String> {
+        let connection = open().map_err(|e| e.to_string())?;
+        let profile: Option<String> = connection.query_row(
+            "SELECT profile FROM sessions WHERE id = ?1",
+            params![session_id],
+            |row| row.get(0),
+        ).ok();
+        let Some(profile) = profile else {
+            return Ok(false);
+        };
+
+        let mut statement = connection.prepare(
+            "SELECT time, 'sample', hp, NULL FROM samples WHERE session_id = ?1
+            UNION ALL SELECT time, 'transition', NULL, field || '=' || value FROM transitions WHERE session_id = ?1
+            UNION ALL SELECT time, 'action', NULL, action || CASE performed WHEN 0 THEN ' (failed)' ELSE '' END
+                FROM actions WHERE session_id = ?1
+            ORDER BY 1",
+        ).map_err(|e| e.to_string())?;
+        let rows = statement.query_map(params![session_id], |row| Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<f64>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+        ))).map_err(|e| e.to_string())?;
+
+        writeln!(output, "session,profile,time,kind,hp,detail").map_err(|e| e.to_string())?;
+        for row in rows {
+            let (time, kind, hp, detail) = row.map_err(|e| e.to_string())?;
+            writeln!(
+                output,
+                "{},{},{},{},{},{}",
+                session_id,
+                csv_field(&profile),
+                time,
+                kind,
+                hp.map_or(String::new(), |hp| format!("{:.2}", hp)),
+                csv_field(detail.as_deref().unwrap_or("")),
+            ).map_err(|e| e.to_string())?;
+        }
+        Ok(true)
+    }
+}
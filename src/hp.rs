@@ -1,4 +1,4 @@
-use std::{ffi::CString, ptr::null_mut};
+use std::{ffi::{CStr, CString}, ptr::null_mut};
 
 use screenshots::{image::{ImageBuffer, Rgba}, Screen};
 use winapi::{shared::windef::{HWND, RECT}, um::winuser::{FindWindowA, GetWindowRect}};
@@ -8,9 +8,29 @@ use crate::config::CurrentHpState;
 const GREEN_HP: Rgba<u8> = Rgba([48, 199, 141, 255]);
 const RED_HP: Rgba<u8> = Rgba([210, 106, 92, 255]);
 
+/// Handle of the top-level window with the given title, null if there is none.
+pub fn find_window(window_name: &CStr) -> HWND {
+    unsafe { FindWindowA(null_mut(), window_name.as_ptr()) }
+}
+
+/// Screen rectangle of the top-level window with the given title, if it exists.
+pub fn find_window_rect(window_name: &CStr) -> Option<RECT> {
+    let window = find_window(window_name);
+    let mut rect: RECT = RECT { left: 0, top: 0, right: 0, bottom: 0 };
+
+    if !window.is_null() {
+        unsafe { GetWindowRect(window, &mut rect) };
+        Some(rect)
+    } else {
+        None
+    }
+}
+
 pub struct HpBarFinder {
     window_name: CString,
     geometry: Option<RECT>,
+    bar_start: Option<[i32; 2]>,
+    capture_duration: std::time::Duration,
 }
 
 impl HpBarFinder {
@@ -18,18 +38,20 @@ impl HpBarFinder {
         HpBarFinder { 
             window_name: CString::new(window_name).unwrap(),
             geometry: None,
+            bar_start: None,
+            capture_duration: std::time::Duration::default(),
         }
     }
     
     fn get_geometry(&self) -> Option<RECT> {
-        let window: HWND = unsafe { FindWindowA(null_mut(), self.window_name.as_ptr()) };
-        let mut rect: RECT = RECT { left: 0, top: 0, right: 0, bottom: 0 };
-        
-        if !window.is_null() {
-            unsafe { GetWindowRect(window, &mut rect) };
-            Some(rect)
-        } else {
-            None
+        find_window_rect(&self.window_name)
+    }
+
+    /// Screen coordinates of the captured area's top-left corner.
+    fn capture_origin(&self) -> [i32; 2] {
+        match self.geometry {
+            Some(rect) => [rect.left + 5, rect.top + 10],
+            None => [0, 0],
         }
     }
     
@@ -63,17 +85,37 @@ impl HpBarFinder {
     pub fn window_was_found(&self) -> bool {
         self.geometry.is_some()
     }
+
+    /// Screen coordinates of the watched window's top-left corner as of the last `get_hp`.
+    pub fn window_origin(&self) -> Option<[i32; 2]> {
+        self.geometry.map(|rect| [rect.left, rect.top])
+    }
+
+    /// How long the screenshot of the last `get_hp` took.
+    pub fn capture_duration(&self) -> std::time::Duration {
+        self.capture_duration
+    }
+
+    /// Screen coordinates of the first HP bar pixel as of the last `get_hp`.
+    pub fn hp_bar_origin(&self) -> Option<[i32; 2]> {
+        self.bar_start
+    }
     
     fn get_hp_bar(&mut self) -> Option<Vec<u8>> {
         self.geometry = self.get_geometry();
+        let capture_start = std::time::Instant::now();
         let image = self.get_screen_image();
+        self.capture_duration = capture_start.elapsed();
         
         let bar_start;
         if let Some(coords) = self.find_hp_bar_start(&image) {
             bar_start = coords;
         } else {
+            self.bar_start = None;
             return None;
         }
+        let origin = self.capture_origin();
+        self.bar_start = Some([origin[0] + bar_start[0] as i32, origin[1] + bar_start[1] as i32]);
         let hp_bar_height = bar_start[1];
         let mut hp_bar_width = bar_start[0];
         let mut hp_bar: Vec<u8> = Vec::new();
@@ -0,0 +1,120 @@
+use std::sync::{Arc, RwLock};
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::config::CurrentState;
+use crate::event_log::{EventLog, LogKind};
+use crate::jsonl_log::{log_state, JsonlLog};
+use crate::metrics::Metrics;
+
+/// How often the server checks whether the app is still running.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+
+/// Optional HTTP server on localhost to check and control the app from
+/// scripts, phones or stream decks. The POST endpoints do what the keys of
+/// the same name do and answer with the new state:
+///
+/// - `GET /state`
+/// - `POST /mute`
+/// - `POST /auto`
+/// - `POST /thieving`
+/// - `POST /quit`
+///
+/// `GET /metrics` serves Prometheus metrics. Live HP samples and state
+/// changes are streamed by `LiveStreamServer` on a port of its own.
+///
+/// Requests with an `Origin` header come from a web page rather than a
+/// script, and are refused so that no website can control the app through
+/// the browser.
+pub struct HttpApi {
+    server: Server,
+    shared_app_state: Arc<RwLock<CurrentState>>,
+    event_log: Arc<RwLock<EventLog>>,
+    jsonl_log: Arc<RwLock<JsonlLog>>,
+}
+
+impl HttpApi {
+    pub fn new(
+        port: u16,
+        shared_app_state: Arc<RwLock<CurrentState>>,
+        event_log: Arc<RwLock<EventLog>>,
+        jsonl_log: Arc<RwLock<JsonlLog>>
+    ) -> Result<Self, String> {
+        let server = Server::http(("127.0.0.1", port))
+            .map_err(|err| format!("Failed to start HTTP API on port {}: {}", port, err))?;
+        event_log.write().unwrap().push(LogKind::Config, format!("HTTP API listening on 127.0.0.1:{}", port));
+
+        Ok(HttpApi {
+            server,
+            shared_app_state,
+            event_log,
+            jsonl_log,
+        })
+    }
+
+    fn response(body: String, content_type: &str, status: u16) -> Response<std::io::Cursor<Vec<u8>>> {
+        let content_type = Header::from_bytes("Content-Type", content_type).unwrap();
+        Response::from_string(body).with_status_code(status).with_header(content_type)
+    }
+
+    fn json_response(body: String, status: u16) -> Response<std::io::Cursor<Vec<u8>>> {
+        Self::response(body, "application/json", status)
+    }
+
+    fn state_response(&self) -> Response<std::io::Cursor<Vec<u8>>> {
+        let snapshot = self.shared_app_state.read().unwrap().snapshot();
+        Self::json_response(serde_json::to_string(&snapshot).unwrap(), 200)
+    }
+
+    /// Applies `command` to the shared state, logging what it changed.
+    fn control(&self, command: impl FnOnce(&mut CurrentState)) -> Response<std::io::Cursor<Vec<u8>>> {
+        let (before, after) = {
+            let mut shared_app_state = self.shared_app_state.write().unwrap();
+            let before = *shared_app_state;
+            command(&mut shared_app_state);
+            (before, *shared_app_state)
+        };
+        self.event_log.write().unwrap().push_changes(&before, &after);
+        log_state(&self.jsonl_log, &self.event_log, &after);
+        self.state_response()
+    }
+
+    fn handle(&self, request: Request) {
+        if request.headers().iter().any(|header| header.field.equiv("Origin")) {
+            let response = Self::json_response(r#"{"error":"cross-origin requests are not allowed"}"#.to_string(), 403);
+            request.respond(response).ok();
+            return;
+        }
+        let response = match (request.method(), request.url()) {
+            (Method::Get, "/state") => self.state_response(),
+            (Method::Get, "/metrics") => Self::response(
+                Metrics::render(&self.shared_app_state.read().unwrap()),
+                "text/plain; version=0.0.4",
+                200,
+            ),
+            (Method::Post, "/mute") => self.control(CurrentState::toggle_mute),
+            (Method::Post, "/auto") => self.control(CurrentState::toggle_auto),
+            (Method::Post, "/thieving") => self.control(CurrentState::toggle_thieving),
+            (Method::Post, "/quit") => self.control(|state| state.is_running = false),
+            (_, "/state" | "/metrics" | "/mute" | "/auto" | "/thieving" | "/quit") => {
+                Self::json_response(r#"{"error":"method not allowed"}"#.to_string(), 405)
+            }
+            _ => Self::json_response(r#"{"error":"not found"}"#.to_string(), 404),
+        };
+        request.respond(response).ok();
+    }
+
+    pub fn run(&mut self) {
+        while self.shared_app_state.read().unwrap().is_running {
+            match self.server.recv_timeout(POLL_INTERVAL) {
+                Ok(Some(request)) => self.handle(request),
+                Ok(None) => {}
+                Err(err) => {
+                    self.event_log.write().unwrap().push(LogKind::Error, format!("HTTP API stopped: {}", err));
+                    return;
+                }
+            }
+        }
+    }
+}
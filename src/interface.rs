@@ -1,25 +1,33 @@
-use std::{io::{self, Write}, sync::{Arc, RwLock}};
-use crate::config::{CurrentState, MuteOptions, CurrentHpState, AutoControlMode};
-use crossterm::{queue, cursor, terminal, event};
-use crossterm::event::{Event, KeyCode, KeyEvent};
-use indoc::indoc;
-
-macro_rules! print_line {
-    ($stdout:expr, $s:expr) => {
-        queue!($stdout, cursor::MoveDown(1)).unwrap();
-        queue!($stdout, cursor::MoveToColumn(0)).unwrap();
-        queue!($stdout, terminal::Clear(terminal::ClearType::UntilNewLine)).unwrap();
-        print!("{}", $s);
-    };
-}
+use std::sync::{mpsc, Arc, RwLock};
+use crate::command::{CommandLine, ControlCommand};
+use crate::config::{CurrentState, CurrentHpState, HpHistory, HpMarkers, KeyCommand};
+use crate::event_log::{EventLog, LogKind};
+use crate::jsonl_log::{log_state, JsonlLog};
+use crate::keymap::Keymap;
+use crate::settings::{SettingField, SettingsEditor};
+use crossterm::event;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    symbols::Marker,
+    text::{Line, Span},
+    widgets::{Axis, Block, Chart, Dataset, Gauge, GraphType, Paragraph},
+    Frame,
+};
+
 
 
+/// Full-screen terminal UI. Takes over the terminal (alternate screen, raw mode)
+/// while running and restores it on exit or panic.
 pub struct DisplayInterface {
     shared_app_state: Arc<RwLock<CurrentState>>,
+    event_log: Arc<RwLock<EventLog>>,
+    hp_history: Arc<RwLock<HpHistory>>,
+    command_line: Arc<RwLock<CommandLine>>,
+    settings_editor: Arc<RwLock<SettingsEditor>>,
+    key_help: Vec<String>,
     app_state: CurrentState,
-    dynamic_part: String,
-    static_part: String,
-    stdout: io::Stdout,
     tick_rate: std::time::Duration,
 }
 
@@ -27,106 +35,292 @@ pub struct DisplayInterface {
 impl DisplayInterface {
     pub fn new(
         shared_app_state: Arc<RwLock<CurrentState>>,
+        event_log: Arc<RwLock<EventLog>>,
+        hp_history: Arc<RwLock<HpHistory>>,
+        command_line: Arc<RwLock<CommandLine>>,
+        settings_editor: Arc<RwLock<SettingsEditor>>,
+        keymap: &Keymap,
         tick_rate: std::time::Duration,
     ) -> Self {
         let app_state = *shared_app_state.read().unwrap();
-        let dynamic_part = format!(indoc! {r#"
-            Hp: {}
-            OnTopReplica found: {}
-
-            Mutted: {}
-            Auto mod: {}
-            Is thieveing active: {}
-            "#}, 
-            0, 
-            app_state.on_top_replica_found,
-            match app_state.is_muted {
-                MuteOptions::Mute => "Yes",
-                MuteOptions::TempMute => "Temporarily",
-                MuteOptions::Unmute => "No",
-            },
-            app_state.auto_control,
-            match app_state.is_thieving_active {
-                true => "Yes",
-                false => "No",
-            }
-        );
-        let static_part = format!(indoc! {r#"
-        
-            M|m: Mute on/off
-            Esc|T|t: Temporarily mute on/off
-            A|a: Auto mode on/off
-            S|s: Temrorarily auto mode on/off
-            B|b: Thiefing on/off
-            Q|q: Quit"#
-        });
 
         DisplayInterface {
             shared_app_state,
+            event_log,
+            hp_history,
+            command_line,
+            settings_editor,
+            key_help: keymap.help_lines().to_vec(),
             tick_rate,
             app_state,
-            dynamic_part,
-            static_part,
-            stdout: io::stdout(),
         }
     }
-    
-    pub fn draw(&self) {
-        println!("\n{}{}", self.dynamic_part, self.static_part); 
-    }
 
     pub fn update_app_state(&mut self) {
         self.app_state = *self.shared_app_state.read().unwrap();
     }
 
-    pub fn update(&mut self) {
-        self.draw();
-        while self.app_state.is_running {
-            self.update_app_state();
-            let dynamic_part_lines = self.dynamic_part.lines().into_iter().count() as u16;
-            let static_part_lines = self.static_part.lines().into_iter().count() as u16;
-
-            queue!(self.stdout, cursor::MoveUp(dynamic_part_lines + static_part_lines + 1)).unwrap();
-
-            print_line!(self.stdout, format!("Hp: {}", match self.app_state.hp {
-                CurrentHpState::Hp(hp) => format!("{:.2}%", hp),
-                CurrentHpState::BarNotFound => "HP bar not found".to_string(),
-            }));
-
-            print_line!(self.stdout, format!("OnTopReplica found: {}", self.app_state.on_top_replica_found));
-            print_line!(self.stdout, "");
-            print_line!(self.stdout, format!("Mutted: {}", match self.app_state.is_muted {
-                MuteOptions::Mute => "Yes",
-                MuteOptions::TempMute => "Temporarily",
-                MuteOptions::Unmute => "No",
-            }));
-            print_line!(self.stdout, format!("Auto mod: {}", self.app_state.auto_control));
-
-            print_line!(self.stdout, format!("Is thieveing active: {}", match self.app_state.is_thieving_active {
+    fn status_lines(&self) -> Vec<Line<'static>> {
+        let mut lines = vec![
+            Line::from(format!("OnTopReplica found: {}", self.app_state.on_top_replica_found)),
+            Line::from(format!("Mutted: {}", self.app_state.is_muted)),
+            Line::from(format!("Auto mod: {}", self.app_state.auto_control)),
+            Line::from(format!("Is thieveing active: {}", match self.app_state.is_thieving_active {
                 true => "Yes",
                 false => "No",
-            }));
+            })),
+            Line::from(format!("Input deferred: {}", match self.app_state.input_deferred {
+                Some(wait) => format!("waiting {}s for user to go idle", wait.as_secs() + 1),
+                None => "No".to_string(),
+            })),
+            Line::from(format!("Safety stop: {}", match self.app_state.safety_stop {
+                Some(reason) => reason.to_string(),
+                None => "No".to_string(),
+            })),
+            Line::from(format!("Audio: {}", match self.app_state.audio_issue {
+                Some(issue) => issue.to_string(),
+                None => "Ok".to_string(),
+            })),
+            Line::from(format!("Schedule: {}", self.app_state.schedule)),
+            Line::from(format!("Paused: {}", match self.app_state.paused_until {
+                Some(until) => {
+                    let left = until.saturating_duration_since(std::time::Instant::now()).as_secs();
+                    format!("{}:{:02} left", left / 60, left % 60)
+                }
+                None => "No".to_string(),
+            })),
+        ];
+        if self.app_state.desktop_notifications_failed {
+            lines.push(Line::from("Desktop notifications: Failed"));
+        }
+        lines
+    }
+
+    /// Editor lines with the selected setting highlighted, or `None` while the editor is closed.
+    fn settings_lines(&self) -> Option<Vec<Line<'static>>> {
+        let settings_editor = self.settings_editor.read().unwrap();
+        if !settings_editor.is_open() {
+            return None;
+        }
+        let mut lines: Vec<Line> = SettingField::ALL.iter()
+            .map(|&field| {
+                let line = Line::from(format!(
+                    "{}: {}",
+                    field.name(),
+                    field.value(&self.app_state.settings)
+                ));
+                match field == settings_editor.selected() {
+                    true => line.style(Style::default().add_modifier(Modifier::REVERSED)),
+                    false => line,
+                }
+            })
+            .collect();
+        lines.push(Line::from("Up/Down: Select"));
+        lines.push(Line::from("Left/Right: Change (Shift: x10)"));
+        lines.push(Line::from("Enter: Save config"));
+        lines.push(Line::from("Esc: Close"));
+        Some(lines)
+    }
+
+    fn hp_gauge(&self) -> Gauge<'static> {
+        let gauge = Gauge::default().block(Block::bordered().title("Hp"));
+        match self.app_state.hp {
+            CurrentHpState::Hp(hp) => gauge
+                .gauge_style(Style::default().fg(Color::Green).bg(Color::Red))
+                .ratio((hp as f64 / 100.0).clamp(0.0, 1.0))
+                .label(format!("{:.2}%", hp)),
+            CurrentHpState::BarNotFound => gauge
+                .gauge_style(Style::default().fg(Color::DarkGray))
+                .ratio(0.0)
+                .label("HP bar not found"),
+        }
+    }
+
+    /// `(seconds ago, hp)` points of the HP history, optionally only those
+    /// with a given marker set.
+    fn hp_points(&self, marked: impl Fn(&HpMarkers) -> bool) -> Vec<(f64, f64)> {
+        let now = std::time::Instant::now();
+        self.hp_history.read().unwrap().iter()
+            .filter(|sample| marked(&sample.markers))
+            .filter_map(|sample| match sample.hp {
+                CurrentHpState::Hp(hp) => Some((-(now - sample.time).as_secs_f64(), hp as f64)),
+                CurrentHpState::BarNotFound => None,
+            })
+            .collect()
+    }
+
+    fn draw_hp_chart(&self, frame: &mut Frame, area: ratatui::layout::Rect) {
+        let hp = self.hp_points(|_| true);
+        let stopped = self.hp_points(|markers| markers.thieving_stopped);
+        let started = self.hp_points(|markers| markers.thieving_started);
+        let alerts = self.hp_points(|markers| markers.alert);
+        let span = hp.first().map_or(60.0, |&(x, _)| (-x).max(60.0));
 
-            queue!(self.stdout, cursor::MoveDown(static_part_lines + 1)).unwrap();
-            queue!(self.stdout, cursor::MoveToColumn(0)).unwrap();
-            io::stdout().flush().unwrap();
+        let scatter = |name, color, points| Dataset::default()
+            .name(name)
+            .marker(Marker::Dot)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(color))
+            .data(points);
+        let chart = Chart::new(vec![
+            Dataset::default()
+                .name("Hp")
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Green))
+                .data(&hp),
+            scatter("Stopped", Color::Red, &stopped),
+            scatter("Started", Color::Cyan, &started),
+            scatter("Alert", Color::Yellow, &alerts),
+        ])
+            .block(Block::bordered().title("Hp history"))
+            .x_axis(Axis::default()
+                .bounds([-span, 0.0])
+                .labels([format!("-{}m{:02}s", span as u64 / 60, span as u64 % 60), "now".to_string()]))
+            .y_axis(Axis::default()
+                .bounds([0.0, 100.0])
+                .labels(["0", "50", "100"]));
+        frame.render_widget(chart, area);
+    }
+
+    /// Shows the entries that fit in `area`, newest at the bottom unless scrolled back.
+    fn draw_event_log(&self, frame: &mut Frame, area: ratatui::layout::Rect) {
+        let event_log = self.event_log.read().unwrap();
+        let visible = area.height.saturating_sub(2) as usize;
+        let mut lines: Vec<Line> = event_log.entries()
+            .rev()
+            .skip(event_log.scroll())
+            .take(visible)
+            .map(|entry| {
+                let color = match entry.kind {
+                    LogKind::Action => Color::Cyan,
+                    LogKind::Alert => Color::Yellow,
+                    LogKind::Mode => Color::Magenta,
+                    LogKind::Detection => Color::Blue,
+                    LogKind::Config => Color::Gray,
+                    LogKind::Error => Color::Red,
+                };
+                Line::from(vec![
+                    Span::raw(format!("{} ", entry.time.format("%H:%M:%S"))),
+                    Span::styled(format!("{:<9} ", entry.kind), Style::default().fg(color)),
+                    Span::raw(entry.message.clone()),
+                ])
+            })
+            .collect();
+        lines.reverse();
+
+        let title = match event_log.scroll() {
+            0 => "Events".to_string(),
+            scroll => format!("Events (-{})", scroll),
+        };
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), area);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let status_lines = self.status_lines();
+        let settings_lines = self.settings_lines();
+        let stats_lines = self.app_state.stats.lines();
+        let body_height = status_lines.len()
+            .max(stats_lines.len())
+            .max(self.key_help.len())
+            .max(settings_lines.as_ref().map_or(0, Vec::len));
+        let command_input = self.command_line.read().unwrap().input().map(str::to_string);
+        let [hp_area, chart_area, body_area, command_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(body_height as u16 + 2),
+            Constraint::Length(if command_input.is_some() { 3 } else { 0 }),
+        ]).areas(frame.area());
+        let [chart_area, log_area] = Layout::horizontal([
+            Constraint::Percentage(50),
+            Constraint::Percentage(50),
+        ]).areas(chart_area);
+        let [status_area, stats_area, settings_area, help_area] = Layout::horizontal([
+            Constraint::Min(0),
+            Constraint::Length(stats_lines.iter().map(|line| line.len()).max().unwrap_or(0) as u16 + 2),
+            Constraint::Length(if settings_lines.is_some() { 34 } else { 0 }),
+            Constraint::Length(self.key_help.iter().map(|line| line.chars().count()).max().unwrap_or(0) as u16 + 2),
+        ]).areas(body_area);
+
+        frame.render_widget(self.hp_gauge(), hp_area);
+        self.draw_hp_chart(frame, chart_area);
+        self.draw_event_log(frame, log_area);
+        frame.render_widget(
+            Paragraph::new(status_lines).block(Block::bordered().title("Status")),
+            status_area,
+        );
+        frame.render_widget(
+            Paragraph::new(stats_lines.into_iter().map(Line::from).collect::<Vec<_>>())
+                .block(Block::bordered().title("Session")),
+            stats_area,
+        );
+        if let Some(settings_lines) = settings_lines {
+            frame.render_widget(
+                Paragraph::new(settings_lines).block(Block::bordered().title("Settings")),
+                settings_area,
+            );
+        }
+        frame.render_widget(
+            Paragraph::new(self.key_help.iter().cloned().map(Line::from).collect::<Vec<_>>()).block(Block::bordered().title("Keys")),
+            help_area,
+        );
+        if let Some(input) = command_input {
+            frame.render_widget(
+                Paragraph::new(format!(":{}", input))
+                    .block(Block::bordered().title(format!("Command: {}", ControlCommand::USAGE))),
+                command_area,
+            );
+            frame.set_cursor_position((command_area.x + input.chars().count() as u16 + 2, command_area.y + 1));
+        }
+    }
+
+    pub fn update(&mut self) {
+        let mut terminal = ratatui::init();
+        while self.app_state.is_running {
+            self.update_app_state();
+            terminal.draw(|frame| self.draw(frame)).unwrap();
             std::thread::sleep(self.tick_rate);
         }
+        ratatui::restore();
     }
 }
 
 
+/// How long `KeyboardKeyPressProcessor` waits for a key before checking
+/// whether the app was stopped elsewhere.
+const KEY_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
 pub struct KeyboardKeyPressProcessor {
     shared_app_state: Arc<RwLock<CurrentState>>,
+    event_log: Arc<RwLock<EventLog>>,
+    jsonl_log: Arc<RwLock<JsonlLog>>,
+    command_line: Arc<RwLock<CommandLine>>,
+    settings_editor: Arc<RwLock<SettingsEditor>>,
+    commands: mpsc::Sender<ControlCommand>,
+    keymap: Keymap,
     app_state: CurrentState,
 }
 
 
 impl KeyboardKeyPressProcessor {
-    pub fn new(shared_app_state: Arc<RwLock<CurrentState>>) -> Self {
+    pub fn new(
+        shared_app_state: Arc<RwLock<CurrentState>>,
+        event_log: Arc<RwLock<EventLog>>,
+        jsonl_log: Arc<RwLock<JsonlLog>>,
+        command_line: Arc<RwLock<CommandLine>>,
+        settings_editor: Arc<RwLock<SettingsEditor>>,
+        commands: mpsc::Sender<ControlCommand>,
+        keymap: Keymap,
+    ) -> Self {
         let app_state = *shared_app_state.read().unwrap();
         KeyboardKeyPressProcessor {
             shared_app_state,
+            event_log,
+            jsonl_log,
+            command_line,
+            settings_editor,
+            commands,
+            keymap,
             app_state,
         }
     }
@@ -135,94 +329,113 @@ impl KeyboardKeyPressProcessor {
         self.app_state.update_from(&self.shared_app_state.read().unwrap());
     }
 
-    fn process_event(&self, event: KeyEvent) {
-        if event.kind == event::KeyEventKind::Release {
-            return;
-        };
+    fn log(&self, kind: LogKind, message: impl Into<String>) {
+        self.event_log.write().unwrap().push(kind, message);
+    }
 
+    /// Edits the open command line, sending the command off on Enter.
+    fn process_command_line_event(&self, event: KeyEvent) {
+        let mut command_line = self.command_line.write().unwrap();
         match event.code {
-            KeyCode::Char('M' | 'm' | 'Ь' | 'ь') => {
-                let is_muted = {
-                    self.shared_app_state.write().unwrap().is_muted.clone()
+            KeyCode::Esc => command_line.close(),
+            KeyCode::Backspace => command_line.pop(),
+            KeyCode::Char(c) if !event.modifiers.contains(KeyModifiers::CONTROL) => command_line.push(c),
+            KeyCode::Enter => {
+                let Some(line) = command_line.submit() else {
+                    return;
                 };
-                match is_muted {
-                    MuteOptions::Mute => {
-                        self.shared_app_state.write().unwrap().is_muted = MuteOptions::Unmute;
-                    },
-                    MuteOptions::TempMute => {
-                        self.shared_app_state.write().unwrap().is_muted = MuteOptions::Mute;
-                    },
-                    MuteOptions::Unmute => {
-                        self.shared_app_state.write().unwrap().is_muted = MuteOptions::Mute;
-                    }
+                drop(command_line);
+                if line.trim().is_empty() {
+                    return;
                 }
-            }
-            KeyCode::Char('T' | 't' | 'Е' | 'е') | KeyCode::Esc => {
-                let is_muted = {
-                    self.shared_app_state.write().unwrap().is_muted.clone()
-                };
-                match is_muted {
-                    MuteOptions::Mute => {
-                        self.shared_app_state.write().unwrap().is_muted = MuteOptions::TempMute;
-                    },
-                    MuteOptions::TempMute => {
-                        self.shared_app_state.write().unwrap().is_muted = MuteOptions::Unmute;
-                    },
-                    MuteOptions::Unmute => {
-                        self.shared_app_state.write().unwrap().is_muted = MuteOptions::TempMute;
-                    }
+                match line.parse::<ControlCommand>() {
+                    Ok(command) => self.send_command(command),
+                    Err(err) => self.log(LogKind::Error, err),
                 }
             }
-            KeyCode::Char('A' | 'a' | 'Ф' | 'ф') => {
-                let auto_control = {
-                    self.shared_app_state.write().unwrap().auto_control.clone()
-                };
-                match auto_control {
-                    AutoControlMode::Off => {
-                        self.shared_app_state.write().unwrap().auto_control = AutoControlMode::On;
-                    },
-                    AutoControlMode::On => {
-                        self.shared_app_state.write().unwrap().auto_control = AutoControlMode::Off;
-                    },
-                    AutoControlMode::Temporarily => {
-                        self.shared_app_state.write().unwrap().auto_control = AutoControlMode::On;
-                    }
-                };
-            }
-            KeyCode::Char('S' | 's' | 'Ы' | 'ы') => {
-                let auto_control = {
-                    self.shared_app_state.write().unwrap().auto_control.clone()
-                };
-                match auto_control {
-                    AutoControlMode::Off => {
-                        self.shared_app_state.write().unwrap().auto_control = AutoControlMode::Temporarily;
-                    },
-                    AutoControlMode::On => {
-                        self.shared_app_state.write().unwrap().auto_control = AutoControlMode::Temporarily;
-                    },
-                    AutoControlMode::Temporarily => {
-                        self.shared_app_state.write().unwrap().auto_control = AutoControlMode::Off;
-                    }
-                };
-            }
-            KeyCode::Char('B' | 'b' | 'И' | 'и') => {
-                let is_thiving_active = {
-                    self.shared_app_state.read().unwrap().is_thieving_active
-                };
-                self.shared_app_state.write().unwrap().is_thieving_active = !is_thiving_active;
-            }
-            KeyCode::Char('Q' | 'q' | 'Й' | 'й') => {
-                self.shared_app_state.write().unwrap().is_running = false;
-                println!("Exiting...");
-            }
             _ => {}
         }
     }
 
+    fn send_command(&self, command: ControlCommand) {
+        if self.commands.send(command).is_err() {
+            self.log(LogKind::Error, "Automation has stopped, command dropped");
+        }
+    }
+
+    /// Handles the keys of the open settings editor, returning `false` for
+    /// keys it leaves to the keymap.
+    fn process_settings_event(&self, event: KeyEvent) -> bool {
+        let mut settings_editor = self.settings_editor.write().unwrap();
+        let steps = match event.modifiers.contains(KeyModifiers::SHIFT) {
+            true => 10,
+            false => 1,
+        };
+        match event.code {
+            KeyCode::Up => settings_editor.select_by(-1),
+            KeyCode::Down => settings_editor.select_by(1),
+            KeyCode::Left | KeyCode::Right => {
+                let steps = if event.code == KeyCode::Left { -steps } else { steps };
+                self.send_command(ControlCommand::Adjust(settings_editor.selected(), steps));
+            }
+            KeyCode::Enter => self.send_command(ControlCommand::SaveConfig),
+            KeyCode::Esc => settings_editor.toggle(),
+            _ => return false,
+        }
+        true
+    }
+
+    fn process_event(&self, event: KeyEvent) {
+        if event.kind == event::KeyEventKind::Release {
+            return;
+        };
+        if self.command_line.read().unwrap().input().is_some() {
+            self.process_command_line_event(event);
+            return;
+        }
+        if self.settings_editor.read().unwrap().is_open() && self.process_settings_event(event) {
+            return;
+        }
+        let Some(command) = self.keymap.command(&event) else {
+            return;
+        };
+        let app_state = *self.shared_app_state.read().unwrap();
+
+        match command {
+            KeyCommand::ToggleMute => self.shared_app_state.write().unwrap().toggle_mute(),
+            KeyCommand::ToggleTempMute => self.shared_app_state.write().unwrap().toggle_temp_mute(),
+            KeyCommand::CycleTimedMute => self.shared_app_state.write().unwrap().cycle_timed_mute(),
+            KeyCommand::ToggleAuto => self.shared_app_state.write().unwrap().toggle_auto(),
+            KeyCommand::ToggleTempAuto => self.shared_app_state.write().unwrap().toggle_temp_auto(),
+            KeyCommand::ToggleThieving => self.shared_app_state.write().unwrap().toggle_thieving(),
+            KeyCommand::Quit => self.shared_app_state.write().unwrap().is_running = false,
+            KeyCommand::ScrollUp => self.event_log.write().unwrap().scroll_by(1),
+            KeyCommand::ScrollDown => self.event_log.write().unwrap().scroll_by(-1),
+            KeyCommand::PageUp => self.event_log.write().unwrap().scroll_by(10),
+            KeyCommand::PageDown => self.event_log.write().unwrap().scroll_by(-10),
+            KeyCommand::ScrollOldest => self.event_log.write().unwrap().scroll_to_oldest(),
+            KeyCommand::ScrollNewest => self.event_log.write().unwrap().scroll_to_newest(),
+            KeyCommand::OpenCommandLine => self.command_line.write().unwrap().open(),
+            KeyCommand::ToggleSettings => self.settings_editor.write().unwrap().toggle(),
+        }
+
+        let new_state = *self.shared_app_state.read().unwrap();
+        self.event_log.write().unwrap().push_changes(&app_state, &new_state);
+        log_state(&self.jsonl_log, &self.event_log, &new_state);
+    }
+
+    /// Handles key presses until the app is stopped, by a key or from anywhere
+    /// else like `/quit` or a crashed thread.
     pub fn update(&mut self) {
-        while self.app_state.is_running {
+        loop {
+            self.update_app_state();
+            if !self.app_state.is_running {
+                break;
+            }
+            if !event::poll(KEY_POLL_INTERVAL).unwrap() {
+                continue;
+            }
             if let Event::Key(event) = event::read().unwrap() {
-                self.update_app_state();
                 self.process_event(event);
             }
         }
@@ -0,0 +1,158 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::RwLock;
+
+use chrono::Local;
+use serde_json::json;
+
+use crate::config::{CurrentState, StateSnapshot};
+use crate::event_log::{EventLog, LogKind};
+
+
+/// Appends state changes and automated actions to a file, one JSON object per
+/// line, for analysis outside the app.
+///
+/// Shared between the control loop, the keys and the HTTP API, so every
+/// change is written when and where it happens. Writes nothing while no file
+/// is open, and closes the file after the first failed write.
+#[derive(Default)]
+pub struct JsonlLog {
+    file: Option<File>,
+    /// Last state written, so unchanged states aren't repeated.
+    last_state: Option<StateSnapshot>,
+}
+
+impl JsonlLog {
+    /// Closes the current file and opens `path` instead, if given.
+    pub fn open(&mut self, path: Option<&str>) -> io::Result<()> {
+        self.file = None;
+        self.last_state = None;
+        if let Some(path) = path {
+            self.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, line: serde_json::Value) -> io::Result<()> {
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+        let result = writeln!(file, "{}", line);
+        if result.is_err() {
+            self.file = None;
+        }
+        result
+    }
+
+    /// Writes `state` if it differs from the last one written.
+    pub fn record_state(&mut self, state: StateSnapshot) -> io::Result<()> {
+        if self.last_state.as_ref() == Some(&state) {
+            return Ok(());
+        }
+        self.write(json!({
+            "time": Local::now().to_rfc3339(),
+            "type": "state",
+            "state": state,
+        }))?;
+        self.last_state = Some(state);
+        Ok(())
+    }
+
+    pub fn record_action(&mut self, action: &str, performed: bool) -> io::Result<()> {
+        self.write(json!({
+            "time": Local::now().to_rfc3339(),
+            "type": "action",
+            "action": action,
+            "performed": performed,
+        }))
+    }
+}
+
+/// Writes `state` to the shared log, reporting a failed write to the event log.
+pub fn log_state(jsonl_log: &RwLock<JsonlLog>, event_log: &RwLock<EventLog>, state: &CurrentState) {
+    if let Err(err) = jsonl_log.write().unwrap().record_state(state.snapshot()) {
+        event_log.write().unwrap().push(LogKind::Error, format!("JSONL log disabled: {}", err));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_temp(name: &str) -> (JsonlLog, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("{}_{}.jsonl", name, std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let mut jsonl_log = JsonlLog::default();
+        jsonl_log.open(path.to_str()).unwrap();
+        (jsonl_log, path)
+    }
+
+    fn records(path: &std::path::Path) -> Vec<serde_json::Value> {
+        let records = std::fs::read_to_string(path).unwrap().lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        std::fs::remove_file(path).ok();
+        records
+    }
+
+    #[test]
+    fn unchanged_states_are_written_once() {
+        let (mut jsonl_log, path) = open_temp("jsonl_log_unchanged");
+        let state = CurrentState::default();
+        jsonl_log.record_state(state.snapshot()).unwrap();
+        jsonl_log.record_state(state.snapshot()).unwrap();
+
+        let records = records(&path);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["type"], "state");
+    }
+
+    #[test]
+    fn changed_states_are_written_again() {
+        let (mut jsonl_log, path) = open_temp("jsonl_log_changed");
+        let mut state = CurrentState::default();
+        jsonl_log.record_state(state.snapshot()).unwrap();
+        state.toggle_mute();
+        jsonl_log.record_state(state.snapshot()).unwrap();
+        state.toggle_mute();
+        jsonl_log.record_state(state.snapshot()).unwrap();
+
+        assert_eq!(records(&path).len(), 3);
+    }
+
+    #[test]
+    fn actions_do_not_reset_deduplication() {
+        let (mut jsonl_log, path) = open_temp("jsonl_log_actions");
+        let state = CurrentState::default();
+        jsonl_log.record_state(state.snapshot()).unwrap();
+        jsonl_log.record_action("Toggle thieving", true).unwrap();
+        jsonl_log.record_state(state.snapshot()).unwrap();
+
+        let records = records(&path);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["type"], "action");
+        assert_eq!(records[1]["performed"], true);
+    }
+
+    #[test]
+    fn reopening_writes_the_state_to_the_new_file() {
+        let (mut jsonl_log, first) = open_temp("jsonl_log_first");
+        let state = CurrentState::default();
+        jsonl_log.record_state(state.snapshot()).unwrap();
+        let second = std::env::temp_dir().join(format!("jsonl_log_second_{}.jsonl", std::process::id()));
+        std::fs::remove_file(&second).ok();
+        jsonl_log.open(second.to_str()).unwrap();
+        jsonl_log.record_state(state.snapshot()).unwrap();
+
+        assert_eq!(records(&first).len(), 1);
+        assert_eq!(records(&second).len(), 1);
+    }
+
+    #[test]
+    fn closed_log_writes_nothing() {
+        let mut jsonl_log = JsonlLog::default();
+        jsonl_log.record_state(CurrentState::default().snapshot()).unwrap();
+        jsonl_log.record_action("Toggle thieving", true).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+use std::ptr::null_mut;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use winapi::shared::minwindef::HKL;
+use winapi::um::winuser::{GetKeyboardLayoutList, VkKeyScanExW};
+
+use crate::config::{KeyBinding, KeyCommand};
+
+
+/// A parsed `KeyBinding::keys`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct KeyChord {
+    modifiers: KeyModifiers,
+    code: KeyCode,
+}
+
+impl KeyChord {
+    fn parse(keys: &str, layouts: &Layouts) -> Result<Self, String> {
+        let mut parts: Vec<&str> = keys.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|key| !key.is_empty())
+            .ok_or_else(|| format!("No key in \"{}\"", keys))?;
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in parts {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("Unknown modifier \"{}\" in \"{}\"", modifier, keys)),
+            };
+        }
+
+        let code = match key.to_lowercase().as_str() {
+            "esc" => KeyCode::Esc,
+            "enter" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "space" => KeyCode::Char(' '),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "insert" => KeyCode::Insert,
+            "delete" => KeyCode::Delete,
+            name => {
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(layouts.physical_char(c)),
+                    (Some('f'), Some(_)) => name[1..].parse().ok()
+                        .filter(|n| (1..=24).contains(n))
+                        .map(KeyCode::F)
+                        .ok_or_else(|| format!("Unknown key \"{}\" in \"{}\"", key, keys))?,
+                    _ => return Err(format!("Unknown key \"{}\" in \"{}\"", key, keys)),
+                }
+            }
+        };
+        Ok(KeyChord { modifiers, code })
+    }
+
+    fn matches(&self, event: &KeyEvent, layouts: &Layouts) -> bool {
+        let mut modifiers = event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match event.code {
+            KeyCode::Char(c) => {
+                // Shift only picks the case of a letter unless the chord asks for it.
+                if !self.modifiers.contains(KeyModifiers::SHIFT) {
+                    modifiers.remove(KeyModifiers::SHIFT);
+                }
+                KeyCode::Char(layouts.physical_char(c))
+            }
+            code => code,
+        };
+        self.modifiers == modifiers && self.code == code
+    }
+}
+
+
+/// Keyboard layouts installed when the keymap was built. The handles are kept
+/// as plain integers so the keymap can move between threads.
+#[derive(Debug, Clone, Default)]
+struct Layouts(Vec<usize>);
+
+impl Layouts {
+    fn installed() -> Self {
+        let count = unsafe { GetKeyboardLayoutList(0, null_mut()) };
+        let mut layouts: Vec<HKL> = vec![null_mut(); count.max(0) as usize];
+        let count = unsafe { GetKeyboardLayoutList(count, layouts.as_mut_ptr()) };
+        layouts.truncate(count.max(0) as usize);
+        Layouts(layouts.into_iter().map(|layout| layout as usize).collect())
+    }
+
+    /// Maps a typed character to the Latin letter or digit on the same physical
+    /// key, checking every layout, so `ь` on a Russian layout counts as `M`.
+    /// ASCII characters are only lowercased.
+    fn physical_char(&self, c: char) -> char {
+        let c = c.to_lowercase().next().unwrap_or(c);
+        if c.is_ascii() {
+            return c;
+        }
+        let Ok(c16) = u16::try_from(c as u32) else {
+            return c;
+        };
+
+        self.0.iter()
+            .filter_map(|&layout| {
+                let scan = unsafe { VkKeyScanExW(c16, layout as HKL) };
+                // Low byte is the virtual-key code, -1 if the layout has no such character.
+                match (scan & 0xff) as u8 {
+                    vk @ (b'A'..=b'Z' | b'0'..=b'9') if scan != -1 => Some((vk as char).to_ascii_lowercase()),
+                    _ => None,
+                }
+            })
+            .next()
+            .unwrap_or(c)
+    }
+}
+
+
+/// Key bindings of the terminal UI, parsed from `Config::keymap`.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(KeyChord, KeyCommand)>,
+    help: Vec<String>,
+    layouts: Layouts,
+}
+
+impl Keymap {
+    pub fn new(bindings: &[KeyBinding]) -> Result<Self, String> {
+        Self::with_layouts(bindings, Layouts::installed())
+    }
+
+    fn with_layouts(bindings: &[KeyBinding], layouts: Layouts) -> Result<Self, String> {
+        let chords = bindings.iter()
+            .map(|binding| Ok((KeyChord::parse(&binding.keys, &layouts)?, binding.command)))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // One help line per command, in the order the commands first appear.
+        let mut help: Vec<(KeyCommand, Vec<&str>)> = Vec::new();
+        for binding in bindings {
+            match help.iter_mut().find(|(command, _)| *command == binding.command) {
+                Some((_, keys)) => keys.push(&binding.keys),
+                None => help.push((binding.command, vec![&binding.keys])),
+            }
+        }
+        let help = help.into_iter()
+            .map(|(command, keys)| format!("{}: {}", keys.join("|"), command))
+            .collect();
+
+        Ok(Keymap {
+            bindings: chords,
+            help,
+            layouts,
+        })
+    }
+
+    pub fn command(&self, event: &KeyEvent) -> Option<KeyCommand> {
+        self.bindings.iter()
+            .find(|(chord, _)| chord.matches(event, &self.layouts))
+            .map(|&(_, command)| command)
+    }
+
+    pub fn help_lines(&self) -> &[String] {
+        &self.help
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Without any layouts only ASCII characters match, the same on every machine.
+    fn keymap(bindings: &[KeyBinding]) -> Keymap {
+        Keymap::with_layouts(bindings, Layouts::default()).unwrap()
+    }
+
+    fn binding(keys: &str, command: KeyCommand) -> KeyBinding {
+        KeyBinding { keys: keys.to_string(), command }
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn letters_match_either_case() {
+        let keymap = keymap(&KeyBinding::defaults());
+        assert_eq!(keymap.command(&key(KeyCode::Char('m'), KeyModifiers::NONE)), Some(KeyCommand::ToggleMute));
+        assert_eq!(keymap.command(&key(KeyCode::Char('M'), KeyModifiers::SHIFT)), Some(KeyCommand::ToggleMute));
+        assert_eq!(keymap.command(&key(KeyCode::Char('x'), KeyModifiers::NONE)), None);
+    }
+
+    #[test]
+    fn modifiers_have_to_match() {
+        let keymap = keymap(&KeyBinding::defaults());
+        assert_eq!(keymap.command(&key(KeyCode::Char('c'), KeyModifiers::CONTROL)), Some(KeyCommand::Quit));
+        assert_eq!(keymap.command(&key(KeyCode::Char('c'), KeyModifiers::NONE)), None);
+        assert_eq!(keymap.command(&key(KeyCode::Char('m'), KeyModifiers::ALT)), None);
+    }
+
+    #[test]
+    fn punctuation_matches_as_typed() {
+        let keymap = keymap(&KeyBinding::defaults());
+        assert_eq!(
+            keymap.command(&key(KeyCode::Char(':'), KeyModifiers::SHIFT)),
+            Some(KeyCommand::OpenCommandLine)
+        );
+        assert_eq!(keymap.command(&key(KeyCode::Char(';'), KeyModifiers::NONE)), None);
+    }
+
+    #[test]
+    fn shift_only_matters_when_the_chord_asks_for_it() {
+        let keymap = keymap(&[
+            binding("Shift+Up", KeyCommand::PageUp),
+            binding("Up", KeyCommand::ScrollUp),
+        ]);
+        assert_eq!(keymap.command(&key(KeyCode::Up, KeyModifiers::SHIFT)), Some(KeyCommand::PageUp));
+        assert_eq!(keymap.command(&key(KeyCode::Up, KeyModifiers::NONE)), Some(KeyCommand::ScrollUp));
+    }
+
+    #[test]
+    fn named_and_function_keys_parse() {
+        let keymap = keymap(&[
+            binding("esc", KeyCommand::Quit),
+            binding("F5", KeyCommand::ToggleAuto),
+            binding("Ctrl+Alt+Space", KeyCommand::ToggleThieving),
+        ]);
+        assert_eq!(keymap.command(&key(KeyCode::Esc, KeyModifiers::NONE)), Some(KeyCommand::Quit));
+        assert_eq!(keymap.command(&key(KeyCode::F(5), KeyModifiers::NONE)), Some(KeyCommand::ToggleAuto));
+        assert_eq!(
+            keymap.command(&key(KeyCode::Char(' '), KeyModifiers::CONTROL | KeyModifiers::ALT)),
+            Some(KeyCommand::ToggleThieving)
+        );
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected() {
+        for keys in ["Hyper+X", "Ctrl+", "F25", "Foo", ""] {
+            assert!(
+                Keymap::with_layouts(&[binding(keys, KeyCommand::Quit)], Layouts::default()).is_err(),
+                "{}",
+                keys
+            );
+        }
+    }
+
+    #[test]
+    fn help_lists_every_key_of_a_command_together() {
+        let keymap = keymap(&[
+            binding("Esc", KeyCommand::ToggleTempMute),
+            binding("Q", KeyCommand::Quit),
+            binding("T", KeyCommand::ToggleTempMute),
+        ]);
+        assert_eq!(keymap.help_lines(), ["Esc|T: Temporarily mute on/off", "Q: Quit"]);
+    }
+}
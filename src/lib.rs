@@ -1,4 +1,17 @@
 pub mod config;
 pub mod interface;
 pub mod hp;
-pub mod automatization;
\ No newline at end of file
+pub mod automatization;
+pub mod notifier;
+pub mod schedule;
+pub mod event_log;
+pub mod keymap;
+pub mod command;
+pub mod settings;
+pub mod stats;
+pub mod food;
+pub mod history;
+pub mod jsonl_log;
+pub mod http_api;
+pub mod live_stream;
+pub mod metrics;
@@ -0,0 +1,301 @@
+use std::io::{ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use chrono::Local;
+use serde_json::json;
+use tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tungstenite::{Message, WebSocket};
+
+use crate::config::{CurrentHpState, CurrentState, HpHistory, StateSnapshot};
+use crate::event_log::{EventLog, LogKind};
+
+/// Most clients streamed to at once, each one takes a thread.
+const MAX_CLIENTS: usize = 8;
+/// How long a client may take to send its handshake.
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+/// How often streams push new samples, and how often the server checks
+/// whether the app is still running.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+
+/// Whether `origin` is a page served from this computer, the only kind of
+/// page allowed to open a live stream.
+fn is_local_origin(origin: &str) -> bool {
+    let Some(host) = origin.strip_prefix("http://").or_else(|| origin.strip_prefix("https://")) else {
+        return false;
+    };
+    let host = match host.rsplit_once(':') {
+        Some((host, port)) if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => host,
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+/// Refuses handshakes from pages of other websites.
+struct LocalOrigins;
+
+impl Callback for LocalOrigins {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        match request.headers().get("Origin").map(|origin| origin.to_str().unwrap_or_default()) {
+            Some(origin) if !is_local_origin(origin) => Err(Response::builder()
+                .status(403)
+                .body(Some("Only pages served from this computer may connect".to_string()))
+                .unwrap()),
+            _ => Ok(response),
+        }
+    }
+}
+
+/// Optional WebSocket server on localhost for browser overlays, serving a
+/// `LiveStream` to every client on its own thread.
+///
+/// Clients without an `Origin` header, like scripts, and pages served from
+/// this computer may connect; any other website is refused.
+pub struct LiveStreamServer {
+    listener: TcpListener,
+    shared_app_state: Arc<RwLock<CurrentState>>,
+    event_log: Arc<RwLock<EventLog>>,
+    hp_history: Arc<RwLock<HpHistory>>,
+    clients: Arc<AtomicUsize>,
+}
+
+impl LiveStreamServer {
+    pub fn new(
+        port: u16,
+        shared_app_state: Arc<RwLock<CurrentState>>,
+        event_log: Arc<RwLock<EventLog>>,
+        hp_history: Arc<RwLock<HpHistory>>
+    ) -> Result<Self, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|err| format!("Failed to start live stream on port {}: {}", port, err))?;
+        event_log.write().unwrap().push(LogKind::Config, format!("Live stream listening on ws://127.0.0.1:{}", port));
+
+        Ok(LiveStreamServer {
+            listener,
+            shared_app_state,
+            event_log,
+            hp_history,
+            clients: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Streams to `stream` on its own thread, unless there are too many clients already.
+    fn serve(&self, mut stream: TcpStream) {
+        if self.clients.load(Ordering::SeqCst) >= MAX_CLIENTS {
+            stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").ok();
+            return;
+        }
+        // Accepted sockets may inherit non-blocking mode from the listener.
+        if stream.set_nonblocking(false).and_then(|_| stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))).is_err() {
+            return;
+        }
+        self.clients.fetch_add(1, Ordering::SeqCst);
+        let clients = self.clients.clone();
+        let shared_app_state = self.shared_app_state.clone();
+        let hp_history = self.hp_history.clone();
+        std::thread::spawn(move || {
+            if let Some(mut live_stream) = LiveStream::accept(stream, shared_app_state, hp_history) {
+                live_stream.run();
+            }
+            clients.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    pub fn run(&mut self) {
+        while self.shared_app_state.read().unwrap().is_running {
+            match self.listener.accept() {
+                Ok((stream, _)) => self.serve(stream),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+                Err(err) => {
+                    self.event_log.write().unwrap().push(LogKind::Error, format!("Live stream stopped: {}", err));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Streams HP samples and state changes to one WebSocket client as JSON
+/// messages, read from the same shared state the terminal UI draws:
+///
+/// - `{"type": "state", "time": ..., "state": {...}}` on connect and on every change
+/// - `{"type": "sample", "time": ..., "hp": ..., "markers": {...}}` for every new HP sample
+pub struct LiveStream {
+    socket: WebSocket<TcpStream>,
+    shared_app_state: Arc<RwLock<CurrentState>>,
+    hp_history: Arc<RwLock<HpHistory>>,
+    last_state: Option<StateSnapshot>,
+    last_sample: Option<std::time::Instant>,
+}
+
+impl LiveStream {
+    /// Completes the WebSocket handshake on `stream`, or returns `None` if it
+    /// isn't a WebSocket upgrade or comes from a page of another website.
+    pub fn accept(
+        stream: TcpStream,
+        shared_app_state: Arc<RwLock<CurrentState>>,
+        hp_history: Arc<RwLock<HpHistory>>
+    ) -> Option<Self> {
+        let socket = tungstenite::accept_hdr(stream, LocalOrigins).ok()?;
+        // Reads wait at most one poll interval, so the stream can answer
+        // pings and close frames in between its updates.
+        socket.get_ref().set_read_timeout(Some(POLL_INTERVAL)).ok()?;
+        // Only samples taken from now on are streamed.
+        let last_sample = hp_history.read().unwrap().iter().last().map(|sample| sample.time);
+        Some(LiveStream {
+            socket,
+            shared_app_state,
+            hp_history,
+            last_state: None,
+            last_sample,
+        })
+    }
+
+    fn send(&mut self, message: serde_json::Value) -> tungstenite::Result<()> {
+        self.socket.send(Message::text(message.to_string()))
+    }
+
+    /// Sends whatever changed since the last call.
+    fn send_updates(&mut self, app_state: &CurrentState) -> tungstenite::Result<()> {
+        let now = std::time::Instant::now();
+        let mut new_samples: Vec<_> = self.hp_history.read().unwrap().iter()
+            .rev()
+            .take_while(|sample| self.last_sample.is_none_or(|last| sample.time > last))
+            .copied()
+            .collect();
+        new_samples.reverse();
+        for sample in new_samples {
+            self.send(json!({
+                "type": "sample",
+                "time": (Local::now() - (now - sample.time)).to_rfc3339(),
+                "hp": match sample.hp {
+                    CurrentHpState::Hp(hp) => Some(hp),
+                    CurrentHpState::BarNotFound => None,
+                },
+                "markers": sample.markers,
+            }))?;
+            self.last_sample = Some(sample.time);
+        }
+
+        let state = app_state.snapshot();
+        if self.last_state.as_ref() != Some(&state) {
+            self.send(json!({
+                "type": "state",
+                "time": Local::now().to_rfc3339(),
+                "state": state,
+            }))?;
+            self.last_state = Some(state);
+        }
+        Ok(())
+    }
+
+    /// Streams until the app quits or the client goes away.
+    pub fn run(&mut self) {
+        loop {
+            match self.socket.read() {
+                // Messages from the client are ignored, `read` answers pings
+                // and close frames itself.
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(err)) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => return,
+            }
+            let app_state = *self.shared_app_state.read().unwrap();
+            if self.send_updates(&app_state).is_err() {
+                return;
+            }
+            if !app_state.is_running {
+                self.socket.close(None).ok();
+                self.socket.flush().ok();
+                return;
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use tungstenite::client::IntoClientRequest;
+
+    use super::*;
+
+    #[test]
+    fn only_local_origins_are_allowed() {
+        assert!(is_local_origin("http://localhost"));
+        assert!(is_local_origin("http://localhost:8080"));
+        assert!(is_local_origin("https://127.0.0.1:3000"));
+        assert!(is_local_origin("http://[::1]:3000"));
+        assert!(!is_local_origin("https://example.com"));
+        assert!(!is_local_origin("http://localhost.example.com"));
+        assert!(!is_local_origin("null"));
+        assert!(!is_local_origin("file://"));
+    }
+
+    /// Serves one connection on its own thread, which finishes with whether
+    /// the handshake was accepted once the stream ends.
+    fn serve_one() -> (std::net::SocketAddr, std::thread::JoinHandle<bool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let shared_app_state = Arc::new(RwLock::new(CurrentState::default()));
+            let hp_history = Arc::new(RwLock::new(HpHistory::new(std::time::Duration::from_secs(60))));
+            match LiveStream::accept(stream, shared_app_state, hp_history) {
+                Some(mut live_stream) => {
+                    live_stream.run();
+                    true
+                }
+                None => false,
+            }
+        });
+        (address, server)
+    }
+
+    fn connect(address: std::net::SocketAddr, origin: Option<&str>) -> tungstenite::Result<WebSocket<TcpStream>> {
+        let mut request = format!("ws://{}/", address).into_client_request().unwrap();
+        if let Some(origin) = origin {
+            request.headers_mut().insert("Origin", origin.parse().unwrap());
+        }
+        let stream = TcpStream::connect(address).unwrap();
+        tungstenite::client(request, stream).map(|(socket, _)| socket).map_err(|err| match err {
+            tungstenite::HandshakeError::Failure(err) => err,
+            tungstenite::HandshakeError::Interrupted(_) => panic!("handshake interrupted"),
+        })
+    }
+
+    #[test]
+    fn streams_the_state_until_the_client_closes() {
+        let (address, server) = serve_one();
+        let mut client = connect(address, Some("http://localhost:8080")).unwrap();
+
+        let message = client.read().unwrap();
+        let message: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        assert_eq!(message["type"], "state");
+
+        client.close(None).unwrap();
+        while client.read().is_ok() {}
+        // The app is still running, so the stream only ends because it read the close frame.
+        assert!(server.join().unwrap());
+    }
+
+    #[test]
+    fn other_websites_are_refused() {
+        let (address, server) = serve_one();
+        match connect(address, Some("https://example.com")) {
+            Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 403),
+            other => panic!("expected a refused handshake, got {:?}", other.map(|_| ())),
+        }
+        assert!(!server.join().unwrap());
+    }
+
+    #[test]
+    fn plain_http_requests_are_refused() {
+        let (address, server) = serve_one();
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        assert!(!server.join().unwrap());
+    }
+}
@@ -1,14 +1,42 @@
-use std::sync::{Arc, RwLock};
+use std::sync::{mpsc, Arc, PoisonError, RwLock};
+use std::fs;
 use std::io::{self, Write};
-use std::thread;
+use std::thread::{self, JoinHandle};
 use ctrlc;
 
-use mlv_screensaver::config::{Config, CurrentState};
+use mlv_screensaver::command::CommandLine;
+use mlv_screensaver::config::{Config, CurrentState, HpHistory, KeyBinding};
+use mlv_screensaver::event_log::{EventLog, LogKind};
+use mlv_screensaver::history::History;
+use mlv_screensaver::http_api::HttpApi;
+use mlv_screensaver::jsonl_log::JsonlLog;
+use mlv_screensaver::keymap::Keymap;
+use mlv_screensaver::live_stream::LiveStreamServer;
+use mlv_screensaver::settings::SettingsEditor;
 use mlv_screensaver::interface::{DisplayInterface, KeyboardKeyPressProcessor};
-use mlv_screensaver::automatization::AutoControl;
+use mlv_screensaver::automatization::{AutoControl, EmergencyStop};
 
-const MOUSE_COORDS: [i32; 2] = [820, 790];
 
+/// Shuts the app down when dropped by a panicking thread, so the other
+/// threads stop instead of carrying on around a dead one.
+struct StopOnPanic(Arc<RwLock<CurrentState>>);
+
+impl Drop for StopOnPanic {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.write().unwrap_or_else(PoisonError::into_inner).is_running = false;
+        }
+    }
+}
+
+/// Runs `work` on its own thread, shutting the app down if it panics.
+fn spawn_worker(current_state: &Arc<RwLock<CurrentState>>, work: impl FnOnce() + Send + 'static) -> JoinHandle<()> {
+    let stop_on_panic = StopOnPanic(current_state.clone());
+    thread::spawn(move || {
+        let _stop_on_panic = stop_on_panic;
+        work();
+    })
+}
 
 fn get_config() -> Config {
     let mut config = Config::default();
@@ -39,42 +67,167 @@ fn get_config() -> Config {
         config.volume = input_buffer.trim().parse().unwrap();
     }
 
-    config.signal_threshold = config.min_hp * 100 / config.max_hp;
-    config.save_into_file();
+    config.update_signal_threshold();
+    if let Err(err) = config.save_into_file() {
+        println!("Failed to save config: {}", err);
+    }
     config
 }
 
 
+/// `history list` and `history export <session> [file.csv]`.
+fn history_command(args: &[&str]) {
+    match args {
+        ["list"] => match History::sessions() {
+            Ok(sessions) => {
+                for session in sessions {
+                    println!(
+                        "{:>5}  {:<12}  {}  {}  {} samples",
+                        session.id,
+                        session.profile,
+                        session.started,
+                        session.ended.as_deref().unwrap_or("not ended"),
+                        session.samples
+                    );
+                }
+            }
+            Err(err) => println!("Failed to read session history: {}", err),
+        },
+        ["export", session_id, path @ ..] if path.len() <= 1 => {
+            let Ok(session_id) = session_id.parse() else {
+                println!("Invalid session id: {}", session_id);
+                return;
+            };
+            let result = match path {
+                [path] => {
+                    // Exported in memory first, so a missing session doesn't leave an empty file behind.
+                    let mut csv = Vec::new();
+                    History::export_csv(session_id, &mut csv).and_then(|found| {
+                        if found {
+                            fs::write(path, csv).map_err(|e| e.to_string())?;
+                        }
+                        Ok(found)
+                    })
+                }
+                _ => History::export_csv(session_id, &mut io::stdout()),
+            };
+            match result {
+                Ok(true) => {}
+                Ok(false) => println!("No session {}", session_id),
+                Err(err) => println!("Failed to export session {}: {}", session_id, err),
+            }
+        }
+        _ => println!("Usage: history list | history export <session> [file.csv]"),
+    }
+}
+
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if let ["history", history_args @ ..] = args.as_slice() {
+        history_command(history_args);
+        return;
+    }
+
     let config = get_config();
     let current_state = Arc::new(RwLock::new(CurrentState::default()));
+    let event_log = Arc::new(RwLock::new(EventLog::default()));
+    let hp_history = Arc::new(RwLock::new(HpHistory::new(config.hp_history_window())));
+    let jsonl_log = Arc::new(RwLock::new(JsonlLog::default()));
+    let command_line = Arc::new(RwLock::new(CommandLine::default()));
+    let settings_editor = Arc::new(RwLock::new(SettingsEditor::default()));
+    let (command_sender, command_receiver) = mpsc::channel();
     println!("Run with config: {:?}", config);
+    let keymap = Keymap::new(&config.keymap).unwrap_or_else(|err| {
+        println!("Invalid keymap, using the default keys: {}", err);
+        Keymap::new(&KeyBinding::defaults()).unwrap()
+    });
     ctrlc::set_handler({
         let current_state = current_state.clone();
         move || {
-            println!("Exiting...");
             current_state.write().unwrap().is_running = false;
         }
     }).expect("Error setting Ctrl-C handler");
 
+    let http_api = config.http_api_port.map(|port| HttpApi::new(
+        port,
+        current_state.clone(),
+        event_log.clone(),
+        jsonl_log.clone()
+    ));
+    let live_stream = config.live_stream_port.map(|port| LiveStreamServer::new(
+        port,
+        current_state.clone(),
+        event_log.clone(),
+        hp_history.clone()
+    ));
+    let mut emergency_stop = EmergencyStop::new(
+        current_state.clone(),
+        event_log.clone(),
+        config.emergency_stop_hotkey,
+        std::time::Duration::from_millis(50)
+    );
     let mut auto_control = AutoControl::new(
         "OnTopReplica",
         current_state.clone(),
-        config,
-        MOUSE_COORDS,
-        "low_hp.wav",
-        "hight_hp.wav",
-        std::time::Duration::from_millis(1000)
+        event_log.clone(),
+        hp_history.clone(),
+        jsonl_log.clone(),
+        command_receiver,
+        config
     ).unwrap();
     let mut display = DisplayInterface::new(
         current_state.clone(),
+        event_log.clone(),
+        hp_history,
+        command_line.clone(),
+        settings_editor.clone(),
+        &keymap,
         std::time::Duration::from_millis(200)
     );
-    let mut keyboard_processor = KeyboardKeyPressProcessor::new(current_state.clone());
-    let work_handler = thread::spawn(move || {auto_control.run()});
-    let interface_handler = thread::spawn(move || { display.update()});
+    let mut keyboard_processor = KeyboardKeyPressProcessor::new(
+        current_state.clone(),
+        event_log.clone(),
+        jsonl_log,
+        command_line,
+        settings_editor,
+        command_sender,
+        keymap
+    );
+    let mut handlers = vec![
+        spawn_worker(&current_state, move || auto_control.run()),
+        spawn_worker(&current_state, move || display.update()),
+        spawn_worker(&current_state, move || emergency_stop.run()),
+    ];
+    match http_api {
+        Some(Ok(mut http_api)) => handlers.push(spawn_worker(&current_state, move || http_api.run())),
+        Some(Err(err)) => {
+            event_log.write().unwrap().push(LogKind::Error, err);
+        }
+        None => {}
+    }
+    match live_stream {
+        Some(Ok(mut live_stream)) => handlers.push(spawn_worker(&current_state, move || live_stream.run())),
+        Some(Err(err)) => {
+            event_log.write().unwrap().push(LogKind::Error, err);
+        }
+        None => {}
+    }
 
     keyboard_processor.update();
-    work_handler.join().unwrap();
-    interface_handler.join().unwrap();
+    // A thread that panicked already printed why and shut the others down.
+    for handler in handlers {
+        handler.join().ok();
+    }
+
+    let stats = current_state.read().unwrap_or_else(PoisonError::into_inner).stats;
+    println!("Session summary:");
+    for line in stats.lines() {
+        println!("  {}", line);
+    }
+    if let Err(err) = stats.save_summary() {
+        println!("Failed to save session summary: {}", err);
+    }
+    println!("Exiting...");
 }
@@ -0,0 +1,164 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::config::{AutoControlMode, CurrentHpState, CurrentState};
+
+
+/// Upper bounds of the detection latency histogram buckets, in seconds.
+const DETECTION_BUCKETS: [f64; 9] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+/// Measurements behind the Prometheus `/metrics` endpoint that aren't part of
+/// the rest of the state, kept up to date by `AutoControl`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Metrics {
+    /// Detections per bucket of `DETECTION_BUCKETS`, not cumulative.
+    detection_buckets: [u64; DETECTION_BUCKETS.len()],
+    detection_sum: Duration,
+    detection_count: u64,
+    last_capture: Duration,
+    inputs_total: u64,
+}
+
+impl Metrics {
+    /// Accounts for one HP detection that took `latency` in total, `capture` of it
+    /// spent taking the screenshot.
+    pub fn record_detection(&mut self, latency: Duration, capture: Duration) {
+        let secs = latency.as_secs_f64();
+        if let Some(bucket) = DETECTION_BUCKETS.iter().position(|&bound| secs <= bound) {
+            self.detection_buckets[bucket] += 1;
+        }
+        self.detection_sum += latency;
+        self.detection_count += 1;
+        self.last_capture = capture;
+    }
+
+    pub fn record_input(&mut self) {
+        self.inputs_total += 1;
+    }
+
+    /// All metrics in the Prometheus text exposition format.
+    pub fn render(state: &CurrentState) -> String {
+        let metrics = &state.metrics;
+        let mut output = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, f64)]| {
+            writeln!(output, "# HELP mlv_screensaver_{} {}", name, help).unwrap();
+            writeln!(output, "# TYPE mlv_screensaver_{} {}", name, kind).unwrap();
+            for (suffix, value) in samples {
+                writeln!(output, "mlv_screensaver_{}{} {}", name, suffix, value).unwrap();
+            }
+        };
+
+        // Without a sample the gauge is absent rather than NaN while the bar isn't found.
+        let hp: Vec<_> = match state.hp {
+            CurrentHpState::Hp(hp) => vec![(String::new(), hp as f64)],
+            CurrentHpState::BarNotFound => Vec::new(),
+        };
+        metric("hp_percent", "gauge", "Current HP in percent, absent while the bar isn't found.", &hp);
+
+        let mut detection = Vec::new();
+        let mut cumulative = 0;
+        for (bound, count) in DETECTION_BUCKETS.iter().zip(metrics.detection_buckets) {
+            cumulative += count;
+            detection.push((format!("_bucket{{le=\"{}\"}}", bound), cumulative as f64));
+        }
+        detection.push(("_bucket{le=\"+Inf\"}".to_string(), metrics.detection_count as f64));
+        detection.push(("_sum".to_string(), metrics.detection_sum.as_secs_f64()));
+        detection.push(("_count".to_string(), metrics.detection_count as f64));
+        metric("detection_latency_seconds", "histogram", "Time to capture the window and read HP from it.", &detection);
+
+        metric("capture_duration_seconds", "gauge", "Time the last screenshot took.", &[
+            (String::new(), metrics.last_capture.as_secs_f64()),
+        ]);
+        metric("inputs_total", "counter", "Automated clicks and key presses performed.", &[
+            (String::new(), metrics.inputs_total as f64),
+        ]);
+        metric("alerts_total", "counter", "Alerts fired.", &[
+            (String::new(), state.stats.alerts as f64),
+        ]);
+        metric("bar_not_found_seconds_total", "counter", "Time the HP bar couldn't be found.", &[
+            (String::new(), state.stats.bar_not_found.as_secs_f64()),
+        ]);
+
+        let modes = [
+            ("on", AutoControlMode::On),
+            ("off", AutoControlMode::Off),
+            ("temporarily", AutoControlMode::Temporarily),
+        ];
+        let modes: Vec<_> = modes.iter()
+            .map(|&(label, mode)| (
+                format!("{{mode=\"{}\"}}", label),
+                if state.auto_control == mode { 1.0 } else { 0.0 },
+            ))
+            .collect();
+        metric("auto_mode", "gauge", "1 for the current auto mode, 0 for the others.", &modes);
+
+        output
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples of the rendered metrics, without the HELP and TYPE lines.
+    fn samples(state: &CurrentState) -> Vec<String> {
+        Metrics::render(state).lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn sample(state: &CurrentState, name: &str) -> Option<String> {
+        samples(state).into_iter()
+            .find(|line| line.starts_with(&format!("mlv_screensaver_{} ", name)))
+    }
+
+    #[test]
+    fn hp_is_absent_while_the_bar_is_not_found() {
+        let mut state = CurrentState { hp: CurrentHpState::BarNotFound, ..CurrentState::default() };
+        assert_eq!(sample(&state, "hp_percent"), None);
+        assert!(Metrics::render(&state).contains("# TYPE mlv_screensaver_hp_percent gauge"));
+
+        state.hp = CurrentHpState::Hp(42.5);
+        assert_eq!(sample(&state, "hp_percent").as_deref(), Some("mlv_screensaver_hp_percent 42.5"));
+    }
+
+    #[test]
+    fn inputs_are_counted() {
+        let mut state = CurrentState::default();
+        state.metrics.record_input();
+        state.metrics.record_input();
+        assert_eq!(sample(&state, "inputs_total").as_deref(), Some("mlv_screensaver_inputs_total 2"));
+    }
+
+    #[test]
+    fn detection_buckets_are_cumulative() {
+        let mut state = CurrentState::default();
+        state.metrics.record_detection(Duration::from_millis(20), Duration::from_millis(5));
+        state.metrics.record_detection(Duration::from_millis(300), Duration::from_millis(7));
+        state.metrics.record_detection(Duration::from_secs(10), Duration::from_millis(9));
+
+        let samples = samples(&state);
+        let bucket = |le: &str| samples.iter()
+            .find(|line| line.starts_with(&format!("mlv_screensaver_detection_latency_seconds_bucket{{le=\"{}\"}} ", le)))
+            .and_then(|line| line.rsplit(' ').next())
+            .map(str::to_string);
+        assert_eq!(bucket("0.01").as_deref(), Some("0"));
+        assert_eq!(bucket("0.025").as_deref(), Some("1"));
+        assert_eq!(bucket("0.5").as_deref(), Some("2"));
+        assert_eq!(bucket("5").as_deref(), Some("2"));
+        assert_eq!(bucket("+Inf").as_deref(), Some("3"));
+        assert!(samples.contains(&"mlv_screensaver_detection_latency_seconds_count 3".to_string()));
+        assert!(samples.contains(&"mlv_screensaver_capture_duration_seconds 0.009".to_string()));
+    }
+
+    #[test]
+    fn only_the_current_auto_mode_is_set() {
+        let state = CurrentState { auto_control: AutoControlMode::Temporarily, ..CurrentState::default() };
+        let samples = samples(&state);
+        assert!(samples.contains(&"mlv_screensaver_auto_mode{mode=\"temporarily\"} 1".to_string()));
+        assert!(samples.contains(&"mlv_screensaver_auto_mode{mode=\"on\"} 0".to_string()));
+        assert!(samples.contains(&"mlv_screensaver_auto_mode{mode=\"off\"} 0".to_string()));
+    }
+}
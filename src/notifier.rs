@@ -0,0 +1,712 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::sync::{mpsc, Arc, RwLock};
+use std::thread::{self, JoinHandle};
+
+use rodio::{self, buffer::SamplesBuffer, cpal::traits::{DeviceTrait, HostTrait}, Source};
+
+use crate::config::{
+    AlertOutput, AlertSound, AudioIssue, Config, CurrentState, LowHpEscalation, SoundEvent, Tone,
+};
+use crate::event_log::{EventLog, LogKind};
+
+#[derive(Debug)]
+pub enum AudioError {
+    OpenFile(String, io::Error),
+    Decode(String, rodio::decoder::DecoderError),
+    OpenDevice(String, rodio::StreamError),
+    CreateSink(String, rodio::PlayError),
+    /// The device alerts were played on was unplugged.
+    DeviceLost(String),
+    /// The audio thread is gone, so alerts can't be delivered anymore.
+    Stopped,
+}
+
+impl Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioError::OpenFile(path, err) => write!(f, "Failed to open {}: {}", path, err),
+            AudioError::Decode(path, err) => write!(f, "Failed to decode {}: {}", path, err),
+            AudioError::OpenDevice(device, err) => write!(f, "Failed to open audio device {}: {}", device, err),
+            AudioError::CreateSink(device, err) => write!(f, "Failed to play on audio device {}: {}", device, err),
+            AudioError::DeviceLost(device) => write!(f, "Audio device {} was disconnected", device),
+            AudioError::Stopped => write!(f, "Audio thread has stopped"),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+#[derive(Clone, Copy)]
+enum Alert {
+    LowHp { volume: f32, urgent: bool, first: bool },
+    HighHp,
+    Event(SoundEvent),
+    SetVolume(f32),
+    /// Play whatever is queued, then stop the audio thread.
+    Finish,
+}
+
+/// One repeat of the low-HP alert as decided by `LowHpAlarm`.
+pub struct AlarmStep {
+    /// Whether this is the first alert since HP dropped.
+    pub first: bool,
+    /// Relative to `Config::volume`.
+    pub volume: f32,
+    pub urgent: bool,
+    pub run_hook: bool,
+}
+
+/// Escalates the low-HP alert the longer HP stays below the threshold.
+pub struct LowHpAlarm {
+    escalation: LowHpEscalation,
+    started: Option<std::time::Instant>,
+    last_alert: Option<std::time::Instant>,
+    repeats: u32,
+    hook_fired: bool,
+}
+
+impl LowHpAlarm {
+    const INTERVAL: std::time::Duration = std::time::Duration::from_secs(3);
+
+    pub fn new(escalation: LowHpEscalation) -> Self {
+        LowHpAlarm {
+            escalation,
+            started: None,
+            last_alert: None,
+            repeats: 0,
+            hook_fired: false,
+        }
+    }
+
+    /// Called on every tick with low HP; returns the alert to play if one is due.
+    pub fn tick(&mut self) -> Option<AlarmStep> {
+        self.tick_at(std::time::Instant::now())
+    }
+
+    fn tick_at(&mut self, now: std::time::Instant) -> Option<AlarmStep> {
+        let low_for = now - *self.started.get_or_insert(now);
+        if self.last_alert.is_some_and(|time| now - time < Self::INTERVAL) {
+            return None;
+        }
+        self.last_alert = Some(now);
+
+        let escalation = &self.escalation;
+        let first = self.repeats == 0;
+        let volume = (escalation.initial_volume + escalation.volume_step * self.repeats as f32).min(1.0);
+        self.repeats += 1;
+        let reached = |secs: u32| secs != 0 && low_for >= std::time::Duration::from_secs(secs as u64);
+        let urgent = reached(escalation.urgent_after_secs);
+        let run_hook = !self.hook_fired &&
+            !escalation.hook_command.is_empty() &&
+            reached(escalation.hook_after_secs);
+        self.hook_fired |= run_hook;
+
+        Some(AlarmStep { first, volume, urgent, run_hook })
+    }
+
+    /// Starts over once HP has recovered.
+    pub fn reset(&mut self) {
+        self.started = None;
+        self.last_alert = None;
+        self.repeats = 0;
+        self.hook_fired = false;
+    }
+}
+
+/// Renders `tone` as mono samples at `sample_rate`, without touching any audio device.
+pub fn render_tone(tone: &Tone, sample_rate: u32) -> Vec<f32> {
+    const FADE_SECS: f32 = 0.005;
+
+    let beep_len = (tone.beep_ms as u64 * sample_rate as u64 / 1000) as usize;
+    let gap_len = (tone.gap_ms as u64 * sample_rate as u64 / 1000) as usize;
+    let fade_len = ((FADE_SECS * sample_rate as f32) as usize).clamp(1, beep_len.max(1));
+    let mut samples = Vec::with_capacity((beep_len + gap_len) * tone.repeats as usize);
+
+    for repeat in 0..tone.repeats {
+        if repeat > 0 {
+            samples.resize(samples.len() + gap_len, 0.0);
+        }
+        let mut phase = 0.0f32;
+        for i in 0..beep_len {
+            let progress = i as f32 / beep_len as f32;
+            let frequency = tone.start_frequency + (tone.end_frequency - tone.start_frequency) * progress;
+            phase = (phase + std::f32::consts::TAU * frequency / sample_rate as f32) % std::f32::consts::TAU;
+            // Short fades at both ends keep the speaker from clicking.
+            let envelope = (i.min(beep_len - 1 - i) as f32 / fade_len as f32).min(1.0);
+            samples.push(phase.sin() * envelope * 0.5);
+        }
+    }
+    samples
+}
+
+/// A sound decoded or synthesized up front so playing it doesn't touch the disk.
+struct SoundBuffer {
+    channels: u16,
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+impl SoundBuffer {
+    const TONE_SAMPLE_RATE: u32 = 44100;
+
+    fn load(path: &str) -> Result<Self, AudioError> {
+        let file = File::open(path).map_err(|err| AudioError::OpenFile(path.to_string(), err))?;
+        let decoder = rodio::Decoder::new(BufReader::new(file))
+            .map_err(|err| AudioError::Decode(path.to_string(), err))?;
+        Ok(SoundBuffer {
+            channels: decoder.channels(),
+            sample_rate: decoder.sample_rate(),
+            samples: decoder.convert_samples().collect(),
+        })
+    }
+
+    fn from_tone(tone: &Tone) -> Self {
+        SoundBuffer {
+            channels: 1,
+            sample_rate: Self::TONE_SAMPLE_RATE,
+            samples: render_tone(tone, Self::TONE_SAMPLE_RATE),
+        }
+    }
+
+    /// Loads `sound`, falling back to the built-in `fallback` tone if its file can't be used.
+    fn from_alert_sound(sound: &AlertSound, fallback: Tone) -> Result<Self, (Self, AudioError)> {
+        match sound {
+            AlertSound::File(path) => Self::load(path).map_err(|err| (Self::from_tone(&fallback), err)),
+            AlertSound::Tone(tone) => Ok(Self::from_tone(tone)),
+        }
+    }
+
+    fn source(&self) -> SamplesBuffer<f32> {
+        SamplesBuffer::new(self.channels, self.sample_rate, self.samples.clone())
+    }
+}
+
+enum Output {
+    Device {
+        _stream: rodio::OutputStream,
+        sink: rodio::Sink,
+        /// `None` if the device has no name to look it up by.
+        name: Option<String>,
+    },
+    TerminalBell,
+    /// Audio alerts are turned off in the config.
+    Disabled,
+}
+
+impl Output {
+    /// Tries the default device, then every other output device, and falls back
+    /// to the terminal bell if none of them can be opened. Every device that
+    /// fails is passed to `report`.
+    fn open(mut report: impl FnMut(AudioError)) -> (Self, Option<AudioIssue>) {
+        let default_name = rodio::cpal::default_host().default_output_device().and_then(|device| device.name().ok());
+        match Self::with_sink(default_name.as_deref(), rodio::OutputStream::try_default()) {
+            Ok(output) => return (output, None),
+            Err(err) => report(err),
+        }
+        let devices = rodio::cpal::default_host().output_devices().into_iter().flatten();
+        for device in devices {
+            let name = device.name().ok();
+            match Self::with_sink(name.as_deref(), rodio::OutputStream::try_from_device(&device)) {
+                Ok(output) => return (output, Some(AudioIssue::FallbackDevice)),
+                Err(err) => report(err),
+            }
+        }
+        (Output::TerminalBell, Some(AudioIssue::TerminalBell))
+    }
+
+    fn with_sink(
+        name: Option<&str>,
+        stream: Result<(rodio::OutputStream, rodio::OutputStreamHandle), rodio::StreamError>
+    ) -> Result<Self, AudioError> {
+        let device = name.unwrap_or("unnamed").to_string();
+        let (stream, handle) = stream.map_err(|err| AudioError::OpenDevice(device.clone(), err))?;
+        let sink = rodio::Sink::try_new(&handle).map_err(|err| AudioError::CreateSink(device, err))?;
+        Ok(Output::Device { _stream: stream, sink, name: name.map(str::to_string) })
+    }
+
+    /// Name of the device if it was unplugged. The stream of an unplugged device
+    /// keeps taking sounds without an error and plays none of them.
+    fn lost_device(&self) -> Option<&str> {
+        let Output::Device { name: Some(name), .. } = self else {
+            return None;
+        };
+        let mut devices = rodio::cpal::default_host().output_devices().ok()?;
+        match devices.any(|device| device.name().is_ok_and(|device| &device == name)) {
+            true => None,
+            false => Some(name),
+        }
+    }
+
+    fn set_volume(&self, volume: f32) {
+        if let Output::Device { sink, .. } = self {
+            sink.set_volume(volume);
+        }
+    }
+
+    fn play(&self, sound: &SoundBuffer, volume: f32) {
+        match self {
+            Output::Device { sink, .. } => sink.append(sound.source().amplify(volume)),
+            Output::TerminalBell => {
+                print!("\x07");
+                io::stdout().flush().ok();
+            }
+            Output::Disabled => {}
+        }
+    }
+}
+
+/// Shows desktop notifications. `Notifier` takes any backend, so the system's
+/// notification service can be swapped out.
+pub trait NotificationBackend: Send {
+    fn show(&self, summary: &str, body: &str, critical: bool) -> Result<(), String>;
+}
+
+/// org.freedesktop.Notifications over D-Bus on Linux, toast notifications on Windows.
+pub struct DesktopNotifications;
+
+impl NotificationBackend for DesktopNotifications {
+    fn show(&self, summary: &str, body: &str, critical: bool) -> Result<(), String> {
+        let mut notification = notify_rust::Notification::new();
+        notification.appname("mlv-screensaver").summary(summary).body(body);
+        #[cfg(all(unix, not(target_os = "macos")))]
+        if critical {
+            notification.urgency(notify_rust::Urgency::Critical);
+        }
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        let _ = critical;
+        notification.show().map(|_| ()).map_err(|err| err.to_string())
+    }
+}
+
+/// Shows the notification for `alert` through `backend`, if it warrants one.
+fn notify_desktop(backend: &dyn NotificationBackend, alert: &Alert) -> Result<(), String> {
+    match alert {
+        Alert::LowHp { first: true, .. } => backend.show("Low HP", "HP dropped below the threshold", true),
+        Alert::HighHp => backend.show("HP recovered", "HP is full again", false),
+        Alert::Event(SoundEvent::BarLost) => backend.show("HP bar lost", "The HP bar can't be found", true),
+        Alert::Event(SoundEvent::WindowLost) => backend.show("Window lost", "The watched window is gone", true),
+        Alert::Event(SoundEvent::ClickFailed) => {
+            backend.show("Automated input failed", "Its target couldn't be found", true)
+        }
+        Alert::Event(SoundEvent::FoodOut) => backend.show("Out of food", "HP isn't going up from eating anymore", true),
+        _ => Ok(()),
+    }
+}
+
+/// Plays alerts on a dedicated audio thread, and shows them as desktop
+/// notifications from a thread of their own if the config asks for it, so a
+/// slow notification service never holds up a sound. Alerts are queued and
+/// played one after another, so notifying never blocks the caller. Missing sound files or
+/// audio devices degrade to simpler alerts, reported through the shared state
+/// with the underlying errors in the event log.
+pub struct Notifier{
+    sender: mpsc::Sender<Alert>,
+    audio_thread: Option<JoinHandle<()>>,
+    notification_thread: Option<JoinHandle<()>>,
+}
+
+impl Notifier {
+    pub fn new(
+        shared_app_state: Arc<RwLock<CurrentState>>,
+        event_log: Arc<RwLock<EventLog>>,
+        config: &Config
+    ) -> Self {
+        Self::with_notifications(shared_app_state, event_log, config, Box::new(DesktopNotifications))
+    }
+
+    /// Like `new`, showing desktop notifications through `notifications`.
+    pub fn with_notifications(
+        shared_app_state: Arc<RwLock<CurrentState>>,
+        event_log: Arc<RwLock<EventLog>>,
+        config: &Config,
+        notifications: Box<dyn NotificationBackend>
+    ) -> Self {
+        let (desktop, notification_thread) = match config.alert_output {
+            AlertOutput::Audio => (None, None),
+            AlertOutput::Desktop | AlertOutput::Both => {
+                let (sender, receiver) = mpsc::channel();
+                let shared_app_state = shared_app_state.clone();
+                let event_log = event_log.clone();
+                let notification_thread = thread::spawn(move || {
+                    Self::show_notifications(receiver, shared_app_state, event_log, notifications)
+                });
+                (Some(sender), Some(notification_thread))
+            }
+        };
+        let (sender, receiver) = mpsc::channel();
+        let config = config.clone();
+        let audio_thread = thread::spawn(move || {
+            Self::play_alerts(receiver, shared_app_state, event_log, config, desktop)
+        });
+        Notifier{
+            sender,
+            audio_thread: Some(audio_thread),
+            notification_thread,
+        }
+    }
+
+    /// Notification thread body: runs until the audio thread, which passes the
+    /// alerts on, stops.
+    fn show_notifications(
+        receiver: mpsc::Receiver<Alert>,
+        shared_app_state: Arc<RwLock<CurrentState>>,
+        event_log: Arc<RwLock<EventLog>>,
+        notifications: Box<dyn NotificationBackend>
+    ) {
+        for alert in receiver {
+            let result = notify_desktop(notifications.as_ref(), &alert);
+            let failed_before = shared_app_state.read().unwrap().desktop_notifications_failed;
+            if let (Err(err), false) = (&result, failed_before) {
+                event_log.write().unwrap().push(LogKind::Error, format!("Desktop notification failed: {}", err));
+            }
+            shared_app_state.write().unwrap().desktop_notifications_failed = result.is_err();
+        }
+    }
+
+    /// Audio thread body: runs until the `Notifier` is dropped or finished.
+    fn play_alerts(
+        receiver: mpsc::Receiver<Alert>,
+        shared_app_state: Arc<RwLock<CurrentState>>,
+        event_log: Arc<RwLock<EventLog>>,
+        config: Config,
+        desktop: Option<mpsc::Sender<Alert>>
+    ) {
+        let log_error = |err: AudioError| event_log.write().unwrap().push(LogKind::Error, err.to_string());
+        // The output stream isn't `Send`, so it has to live on this thread.
+        let (mut output, device_issue) = match config.alert_output {
+            AlertOutput::Desktop => (Output::Disabled, None),
+            AlertOutput::Audio | AlertOutput::Both => Output::open(log_error),
+        };
+        let mut volume = config.volume;
+        output.set_volume(volume);
+        let mut sound_issue = None;
+        let mut load = |sound: &AlertSound, fallback| SoundBuffer::from_alert_sound(sound, fallback)
+            .unwrap_or_else(|(sound, err)| {
+                log_error(err);
+                sound_issue = Some(AudioIssue::MissingSound);
+                sound
+            });
+        let low_hp_sound = load(&config.low_hp_sound, Tone::triple_beep());
+        let urgent_low_hp_sound = load(&config.low_hp_escalation.urgent_sound, Tone::rapid_beep());
+        let high_hp_sound = load(&config.high_hp_sound, Tone::rising_chirp());
+        let event_sounds: HashMap<SoundEvent, (SoundBuffer, f32)> = config.event_sounds.iter()
+            .filter(|(_, event_sound)| event_sound.enabled)
+            .map(|(&event, event_sound)| {
+                (event, (load(&event_sound.sound, event.default_tone()), event_sound.volume))
+            })
+            .collect();
+        shared_app_state.write().unwrap().audio_issue = device_issue.max(sound_issue);
+
+        for alert in receiver {
+            let plays_sound = matches!(alert, Alert::LowHp { .. } | Alert::HighHp | Alert::Event(_));
+            if let Some(name) = output.lost_device().filter(|_| plays_sound) {
+                log_error(AudioError::DeviceLost(name.to_string()));
+                let (reopened, device_issue) = Output::open(log_error);
+                output = reopened;
+                output.set_volume(volume);
+                // Not on the device picked at startup anymore, even if the new one works.
+                let device_issue = device_issue.max(Some(AudioIssue::FallbackDevice));
+                shared_app_state.write().unwrap().audio_issue = device_issue.max(sound_issue);
+            }
+            if let (Some(desktop), true) = (&desktop, plays_sound) {
+                desktop.send(alert).ok();
+            }
+            match alert {
+                Alert::LowHp { volume, urgent: false, .. } => output.play(&low_hp_sound, volume),
+                Alert::LowHp { volume, urgent: true, .. } => output.play(&urgent_low_hp_sound, volume),
+                Alert::HighHp => output.play(&high_hp_sound, 1.0),
+                Alert::Event(event) => {
+                    if let Some((sound, volume)) = event_sounds.get(&event) {
+                        output.play(sound, *volume);
+                    }
+                }
+                Alert::SetVolume(new_volume) => {
+                    volume = new_volume;
+                    output.set_volume(volume);
+                }
+                Alert::Finish => break,
+            }
+        }
+        if let Output::Device { sink, .. } = &output {
+            sink.sleep_until_end();
+        }
+    }
+
+    fn notify(&self, alert: Alert) -> Result<(), AudioError> {
+        self.sender.send(alert).map_err(|_| AudioError::Stopped)
+    }
+
+    pub fn low_hp_notify(&mut self, step: &AlarmStep) -> Result<(), AudioError> {
+        self.notify(Alert::LowHp { volume: step.volume, urgent: step.urgent, first: step.first })
+    }
+
+    pub fn high_hp_notify(&mut self) -> Result<(), AudioError> {
+        self.notify(Alert::HighHp)
+    }
+
+    /// Plays the sound mapped to `event`, if it's enabled in the config.
+    pub fn event_notify(&mut self, event: SoundEvent) -> Result<(), AudioError> {
+        self.notify(Alert::Event(event))
+    }
+
+    /// Changes the overall volume of alerts.
+    pub fn set_volume(&mut self, volume: f32) -> Result<(), AudioError> {
+        self.notify(Alert::SetVolume(volume))
+    }
+
+    /// Waits for queued alerts to finish playing and stops the audio and
+    /// notification threads.
+    pub fn finish(&mut self) {
+        if let Some(audio_thread) = self.audio_thread.take() {
+            if self.notify(Alert::Finish).is_ok() {
+                audio_thread.join().ok();
+            }
+        }
+        if let Some(notification_thread) = self.notification_thread.take() {
+            notification_thread.join().ok();
+        }
+    }
+
+    /// Starts `command` through the shell without waiting for it to finish.
+    pub fn run_hook(&self, command: &str) -> io::Result<()> {
+        std::process::Command::new("cmd").args(["/C", command]).spawn().map(|_| ())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    fn samples_for(ms: u32) -> usize {
+        (ms as u64 * SAMPLE_RATE as u64 / 1000) as usize
+    }
+
+    fn escalation() -> LowHpEscalation {
+        LowHpEscalation {
+            initial_volume: 0.6,
+            volume_step: 0.2,
+            urgent_after_secs: 10,
+            urgent_sound: AlertSound::Tone(Tone::rapid_beep()),
+            hook_after_secs: 20,
+            hook_command: "echo low".to_string(),
+        }
+    }
+
+    fn secs(secs: u64) -> std::time::Duration {
+        std::time::Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn alarm_repeats_every_interval_getting_louder() {
+        let start = std::time::Instant::now();
+        let mut alarm = LowHpAlarm::new(escalation());
+
+        let step = alarm.tick_at(start).unwrap();
+        assert!(step.first);
+        assert_eq!(step.volume, 0.6);
+        assert!(alarm.tick_at(start + secs(1)).is_none());
+        assert!(alarm.tick_at(start + secs(2)).is_none());
+
+        let step = alarm.tick_at(start + secs(3)).unwrap();
+        assert!(!step.first);
+        assert!((step.volume - 0.8).abs() < 1e-6);
+        let step = alarm.tick_at(start + secs(6)).unwrap();
+        assert_eq!(step.volume, 1.0);
+        let step = alarm.tick_at(start + secs(9)).unwrap();
+        assert_eq!(step.volume, 1.0);
+    }
+
+    #[test]
+    fn alarm_turns_urgent_and_runs_the_hook_once() {
+        let start = std::time::Instant::now();
+        let mut alarm = LowHpAlarm::new(escalation());
+
+        let steps: Vec<_> = (0..10).filter_map(|i| alarm.tick_at(start + secs(i * 3))).collect();
+        let urgent: Vec<_> = steps.iter().map(|step| step.urgent).collect();
+        assert_eq!(urgent, [false, false, false, false, true, true, true, true, true, true]);
+        let hooks: Vec<_> = steps.iter().map(|step| step.run_hook).collect();
+        assert_eq!(hooks, [false, false, false, false, false, false, false, true, false, false]);
+    }
+
+    #[test]
+    fn alarm_never_runs_an_empty_hook_or_disabled_escalation() {
+        let start = std::time::Instant::now();
+        let mut alarm = LowHpAlarm::new(LowHpEscalation {
+            urgent_after_secs: 0,
+            hook_command: String::new(),
+            ..escalation()
+        });
+        for i in 0..20 {
+            let step = alarm.tick_at(start + secs(i * 3)).unwrap();
+            assert!(!step.urgent && !step.run_hook);
+        }
+    }
+
+    #[test]
+    fn alarm_starts_over_after_reset() {
+        let start = std::time::Instant::now();
+        let mut alarm = LowHpAlarm::new(escalation());
+        for i in 0..8 {
+            alarm.tick_at(start + secs(i * 3));
+        }
+
+        alarm.reset();
+        let step = alarm.tick_at(start + secs(25)).unwrap();
+        assert!(step.first && !step.urgent && !step.run_hook);
+        assert_eq!(step.volume, 0.6);
+    }
+
+    #[test]
+    fn tone_has_one_beep_per_repeat_with_gaps_between() {
+        let tone = Tone::triple_beep();
+        let samples = render_tone(&tone, SAMPLE_RATE);
+        assert_eq!(samples.len(), 3 * samples_for(tone.beep_ms) + 2 * samples_for(tone.gap_ms));
+    }
+
+    #[test]
+    fn tone_without_repeats_is_empty() {
+        let tone = Tone { repeats: 0, ..Tone::triple_beep() };
+        assert!(render_tone(&tone, SAMPLE_RATE).is_empty());
+    }
+
+    #[test]
+    fn tone_fades_in_and_out_and_gaps_are_silent() {
+        let tone = Tone::triple_beep();
+        let samples = render_tone(&tone, SAMPLE_RATE);
+        let beep_len = samples_for(tone.beep_ms);
+        let gap_len = samples_for(tone.gap_ms);
+
+        for beep in samples.chunks(beep_len + gap_len) {
+            assert_eq!(beep[0], 0.0);
+            assert_eq!(beep[beep_len - 1], 0.0);
+            assert!(beep[beep_len..].iter().all(|&sample| sample == 0.0));
+            // The fades only take a few milliseconds, the middle plays at full level.
+            let start_peak = beep[..10].iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            let middle_peak = beep[beep_len / 4..beep_len * 3 / 4].iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            assert!(start_peak < 0.05, "{}", start_peak);
+            assert!(middle_peak > 0.45 && middle_peak <= 0.5, "{}", middle_peak);
+        }
+    }
+
+    /// Records the summaries of notifications instead of showing them.
+    struct FakeNotifications {
+        shown: Arc<Mutex<Vec<String>>>,
+        fail: bool,
+    }
+
+    impl NotificationBackend for FakeNotifications {
+        fn show(&self, summary: &str, _body: &str, _critical: bool) -> Result<(), String> {
+            self.shown.lock().unwrap().push(summary.to_string());
+            match self.fail {
+                true => Err("no notification server".to_string()),
+                false => Ok(()),
+            }
+        }
+    }
+
+    /// A notifier that only shows desktop notifications, so no audio device is needed.
+    struct DesktopOnly {
+        notifier: Notifier,
+        shared_app_state: Arc<RwLock<CurrentState>>,
+        event_log: Arc<RwLock<EventLog>>,
+        shown: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl DesktopOnly {
+        fn new(fail: bool) -> Self {
+            let shared_app_state = Arc::new(RwLock::new(CurrentState::default()));
+            let event_log = Arc::new(RwLock::new(EventLog::default()));
+            let shown = Arc::new(Mutex::new(Vec::new()));
+            let config = Config { alert_output: AlertOutput::Desktop, ..Config::default() };
+            let notifier = Notifier::with_notifications(
+                shared_app_state.clone(),
+                event_log.clone(),
+                &config,
+                Box::new(FakeNotifications { shown: shown.clone(), fail }),
+            );
+            DesktopOnly { notifier, shared_app_state, event_log, shown }
+        }
+    }
+
+    fn low_hp_step(first: bool) -> AlarmStep {
+        AlarmStep { first, volume: 1.0, urgent: false, run_hook: false }
+    }
+
+    #[test]
+    fn notifies_low_hp_recovery_and_errors_only() {
+        let mut desktop = DesktopOnly::new(false);
+        desktop.notifier.low_hp_notify(&low_hp_step(true)).unwrap();
+        desktop.notifier.low_hp_notify(&low_hp_step(false)).unwrap();
+        desktop.notifier.event_notify(SoundEvent::ThievingStopped).unwrap();
+        desktop.notifier.event_notify(SoundEvent::BarLost).unwrap();
+        desktop.notifier.high_hp_notify().unwrap();
+        desktop.notifier.finish();
+
+        assert_eq!(*desktop.shown.lock().unwrap(), ["Low HP", "HP bar lost", "HP recovered"]);
+        assert!(!desktop.shared_app_state.read().unwrap().desktop_notifications_failed);
+    }
+
+    #[test]
+    fn failed_notifications_are_reported_once() {
+        let mut desktop = DesktopOnly::new(true);
+        desktop.notifier.low_hp_notify(&low_hp_step(true)).unwrap();
+        desktop.notifier.high_hp_notify().unwrap();
+        desktop.notifier.finish();
+
+        assert!(desktop.shared_app_state.read().unwrap().desktop_notifications_failed);
+        let errors: Vec<_> = desktop.event_log.read().unwrap().entries()
+            .filter(|entry| entry.kind == LogKind::Error && entry.message.starts_with("Desktop"))
+            .map(|entry| entry.message.clone())
+            .collect();
+        assert_eq!(errors, ["Desktop notification failed: no notification server"]);
+    }
+
+    /// Blocks every notification until it's released, like a hung notification server.
+    struct HungNotifications {
+        released: Mutex<mpsc::Receiver<()>>,
+    }
+
+    impl NotificationBackend for HungNotifications {
+        fn show(&self, _summary: &str, _body: &str, _critical: bool) -> Result<(), String> {
+            self.released.lock().unwrap().recv().ok();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn hung_notifications_do_not_hold_up_the_audio_thread() {
+        let (release, released) = mpsc::channel();
+        let config = Config { alert_output: AlertOutput::Desktop, ..Config::default() };
+        let mut notifier = Notifier::with_notifications(
+            Arc::new(RwLock::new(CurrentState::default())),
+            Arc::new(RwLock::new(EventLog::default())),
+            &config,
+            Box::new(HungNotifications { released: Mutex::new(released) }),
+        );
+        notifier.low_hp_notify(&low_hp_step(true)).unwrap();
+        notifier.high_hp_notify().unwrap();
+
+        // The audio thread gets through the queue while the first notification hangs.
+        notifier.notify(Alert::Finish).unwrap();
+        notifier.audio_thread.take().unwrap().join().unwrap();
+
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        notifier.finish();
+    }
+
+    #[test]
+    #[ignore = "needs a desktop session, e.g. a notification server on the D-Bus session bus"]
+    fn shows_a_real_desktop_notification() {
+        DesktopNotifications.show("mlv-screensaver", "Test notification", false).unwrap();
+    }
+}
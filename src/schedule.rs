@@ -0,0 +1,94 @@
+use chrono::{Local, NaiveTime};
+
+use crate::config::{Schedule, ScheduleState};
+
+/// Whether `at` lies in `(from, to]`, wrapping around midnight.
+fn passed(from: NaiveTime, to: NaiveTime, at: NaiveTime) -> bool {
+    if from <= to {
+        from < at && at <= to
+    } else {
+        at > from || at <= to
+    }
+}
+
+/// Applies the configured `Schedule` as local time goes by.
+pub struct Scheduler {
+    schedule: Schedule,
+    last_tick: NaiveTime,
+}
+
+impl Scheduler {
+    pub fn new(schedule: Schedule) -> Self {
+        Scheduler {
+            schedule,
+            last_tick: Local::now().time(),
+        }
+    }
+
+    /// Returns the schedule state for the current time, and whether auto mode
+    /// has to be switched off because its scheduled time passed since the last tick.
+    pub fn tick(&mut self) -> (ScheduleState, bool) {
+        self.tick_at(Local::now().time())
+    }
+
+    fn tick_at(&mut self, now: NaiveTime) -> (ScheduleState, bool) {
+        let auto_off = self.schedule.auto_off_at.is_some_and(|at| passed(self.last_tick, now, at));
+        self.last_tick = now;
+
+        let state = ScheduleState {
+            quiet_until: self.schedule.quiet_hours
+                .filter(|quiet_hours| quiet_hours.contains(now))
+                .map(|quiet_hours| quiet_hours.end),
+            auto_off_at: self.schedule.auto_off_at,
+        };
+        (state, auto_off)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TimeRange;
+
+    fn time(hour: u32, min: u32, sec: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, sec).unwrap()
+    }
+
+    fn scheduler(schedule: Schedule, last_tick: NaiveTime) -> Scheduler {
+        Scheduler { schedule, last_tick }
+    }
+
+    #[test]
+    fn auto_off_fires_once_when_its_time_passes() {
+        let schedule = Schedule { auto_off_at: Some(time(22, 0, 0)), ..Schedule::default() };
+        let mut scheduler = scheduler(schedule, time(21, 59, 58));
+
+        assert!(!scheduler.tick_at(time(21, 59, 59)).1);
+        assert!(scheduler.tick_at(time(22, 0, 1)).1);
+        assert!(!scheduler.tick_at(time(22, 0, 2)).1);
+        assert_eq!(scheduler.tick_at(time(22, 0, 3)).0.auto_off_at, Some(time(22, 0, 0)));
+    }
+
+    #[test]
+    fn auto_off_fires_across_midnight() {
+        let schedule = Schedule { auto_off_at: Some(time(0, 0, 0)), ..Schedule::default() };
+        let mut scheduler = scheduler(schedule, time(23, 59, 59));
+
+        assert!(scheduler.tick_at(time(0, 0, 1)).1);
+        assert!(!scheduler.tick_at(time(0, 0, 2)).1);
+    }
+
+    #[test]
+    fn quiet_hours_wrap_past_midnight() {
+        let quiet_hours = TimeRange { start: time(23, 0, 0), end: time(8, 0, 0) };
+        let schedule = Schedule { quiet_hours: Some(quiet_hours), ..Schedule::default() };
+        let mut scheduler = scheduler(schedule, time(12, 0, 0));
+
+        assert_eq!(scheduler.tick_at(time(22, 59, 59)).0.quiet_until, None);
+        assert_eq!(scheduler.tick_at(time(23, 0, 0)).0.quiet_until, Some(time(8, 0, 0)));
+        assert_eq!(scheduler.tick_at(time(3, 0, 0)).0.quiet_until, Some(time(8, 0, 0)));
+        assert_eq!(scheduler.tick_at(time(8, 0, 0)).0.quiet_until, None);
+        assert!(!scheduler.tick_at(time(9, 0, 0)).1);
+    }
+}
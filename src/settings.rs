@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use crate::command::ControlCommand;
+use crate::config::LiveSettings;
+
+
+/// A config value the settings editor can change while running.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SettingField {
+    Volume,
+    SignalThreshold,
+    MinHp,
+    MaxHp,
+    TickRate,
+}
+
+impl SettingField {
+    pub const ALL: [SettingField; 5] = [
+        SettingField::Volume,
+        SettingField::SignalThreshold,
+        SettingField::MinHp,
+        SettingField::MaxHp,
+        SettingField::TickRate,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SettingField::Volume => "Volume",
+            SettingField::SignalThreshold => "Signal threshold",
+            SettingField::MinHp => "Min HP",
+            SettingField::MaxHp => "Max HP",
+            SettingField::TickRate => "Tick rate",
+        }
+    }
+
+    pub fn value(self, settings: &LiveSettings) -> String {
+        match self {
+            SettingField::Volume => format!("{:.2}", settings.volume),
+            SettingField::SignalThreshold => format!("{}%", settings.signal_threshold),
+            SettingField::MinHp => settings.min_hp.to_string(),
+            SettingField::MaxHp => settings.max_hp.to_string(),
+            SettingField::TickRate => format!("{}ms", settings.tick_rate.as_millis()),
+        }
+    }
+
+    /// The command that moves this setting `steps` steps up (or down, if negative).
+    pub fn adjust(self, settings: &LiveSettings, steps: i32) -> ControlCommand {
+        match self {
+            SettingField::Volume => ControlCommand::SetVolume(
+                ((settings.volume * 20.0).round() + steps as f32).clamp(0.0, 20.0) / 20.0
+            ),
+            SettingField::SignalThreshold => ControlCommand::SetThreshold(
+                settings.signal_threshold.saturating_add_signed(steps).min(100)
+            ),
+            SettingField::MinHp => ControlCommand::SetMinHp(
+                settings.min_hp.saturating_add_signed(steps).min(settings.max_hp)
+            ),
+            SettingField::MaxHp => ControlCommand::SetMaxHp(
+                settings.max_hp.saturating_add_signed(steps).max(settings.min_hp).max(1)
+            ),
+            SettingField::TickRate => {
+                let millis = settings.tick_rate.as_millis() as i64 + steps as i64 * 100;
+                ControlCommand::SetTickRate(Duration::from_millis(millis.clamp(100, 10_000) as u64))
+            }
+        }
+    }
+}
+
+
+/// Whether the settings editor of the terminal UI is open and which field is
+/// selected, shared between the key handler and the UI.
+#[derive(Debug, Default)]
+pub struct SettingsEditor {
+    open: bool,
+    selected: usize,
+}
+
+impl SettingsEditor {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    pub fn selected(&self) -> SettingField {
+        SettingField::ALL[self.selected]
+    }
+
+    /// Moves the selection down for positive `rows`, up otherwise, wrapping around.
+    pub fn select_by(&mut self, rows: isize) {
+        let len = SettingField::ALL.len() as isize;
+        self.selected = (self.selected as isize + rows).rem_euclid(len) as usize;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> LiveSettings {
+        LiveSettings {
+            volume: 0.5,
+            signal_threshold: 40,
+            min_hp: 300,
+            max_hp: 1200,
+            tick_rate: Duration::from_millis(1000),
+        }
+    }
+
+    fn volume(volume: f32, steps: i32) -> f32 {
+        match SettingField::Volume.adjust(&LiveSettings { volume, ..settings() }, steps) {
+            ControlCommand::SetVolume(volume) => volume,
+            command => panic!("unexpected {:?}", command),
+        }
+    }
+
+    #[test]
+    fn volume_moves_in_twentieths_within_range() {
+        assert_eq!(volume(0.5, 1), 0.55);
+        assert_eq!(volume(0.5, -10), 0.0);
+        assert_eq!(volume(0.95, 10), 1.0);
+        assert_eq!(volume(0.0, -1), 0.0);
+        // Off-step volumes from the config snap to the nearest step first.
+        assert_eq!(volume(0.52, 1), 0.55);
+    }
+
+    #[test]
+    fn threshold_stays_a_percentage() {
+        let adjust = |signal_threshold, steps| SettingField::SignalThreshold
+            .adjust(&LiveSettings { signal_threshold, ..settings() }, steps);
+        assert_eq!(adjust(99, 10), ControlCommand::SetThreshold(100));
+        assert_eq!(adjust(5, -10), ControlCommand::SetThreshold(0));
+    }
+
+    #[test]
+    fn min_hp_never_passes_max_hp() {
+        let settings = settings();
+        assert_eq!(SettingField::MinHp.adjust(&settings, 10), ControlCommand::SetMinHp(310));
+        let at_max = LiveSettings { min_hp: 1195, ..settings };
+        assert_eq!(SettingField::MinHp.adjust(&at_max, 10), ControlCommand::SetMinHp(1200));
+        let at_zero = LiveSettings { min_hp: 3, ..settings };
+        assert_eq!(SettingField::MinHp.adjust(&at_zero, -10), ControlCommand::SetMinHp(0));
+    }
+
+    #[test]
+    fn max_hp_never_drops_below_min_hp_or_one() {
+        let near_min = LiveSettings { max_hp: 305, ..settings() };
+        assert_eq!(SettingField::MaxHp.adjust(&near_min, -10), ControlCommand::SetMaxHp(300));
+        let without_min = LiveSettings { min_hp: 0, max_hp: 5, ..settings() };
+        assert_eq!(SettingField::MaxHp.adjust(&without_min, -10), ControlCommand::SetMaxHp(1));
+    }
+
+    #[test]
+    fn tick_rate_moves_in_100ms_steps_within_bounds() {
+        let adjust = |millis, steps| SettingField::TickRate
+            .adjust(&LiveSettings { tick_rate: Duration::from_millis(millis), ..settings() }, steps);
+        assert_eq!(adjust(1000, 1), ControlCommand::SetTickRate(Duration::from_millis(1100)));
+        assert_eq!(adjust(200, -10), ControlCommand::SetTickRate(Duration::from_millis(100)));
+        assert_eq!(adjust(9_950, 10), ControlCommand::SetTickRate(Duration::from_millis(10_000)));
+    }
+}
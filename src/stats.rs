@@ -0,0 +1,199 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+
+use crate::config::CurrentHpState;
+
+
+/// Summaries of finished sessions are appended here, one JSON object per line.
+const SUMMARY_FILE: &str = "session_summaries.jsonl";
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// What happened during the current run, kept up to date by `AutoControl`.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionStats {
+    pub started: DateTime<Local>,
+    pub thieving: Duration,
+    /// Time between an automated stop and the next automated start.
+    pub healing: Duration,
+    /// Completed stop/start cycles.
+    pub cycles: u32,
+    pub alerts: u32,
+    pub lowest_hp: Option<f32>,
+    pub bar_not_found: Duration,
+    hp_sum: f64,
+    hp_samples: u64,
+    is_healing: bool,
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        SessionStats {
+            started: Local::now(),
+            thieving: Duration::default(),
+            healing: Duration::default(),
+            cycles: 0,
+            alerts: 0,
+            lowest_hp: None,
+            bar_not_found: Duration::default(),
+            hp_sum: 0.0,
+            hp_samples: 0,
+            is_healing: false,
+        }
+    }
+}
+
+impl SessionStats {
+    /// Accounts for one tick of `elapsed` time that ended with the given state.
+    pub fn record_tick(&mut self, elapsed: Duration, hp: CurrentHpState, is_thieving_active: bool) {
+        match hp {
+            CurrentHpState::Hp(hp) => {
+                self.hp_sum += hp as f64;
+                self.hp_samples += 1;
+                self.lowest_hp = Some(self.lowest_hp.map_or(hp, |lowest| lowest.min(hp)));
+            }
+            CurrentHpState::BarNotFound => self.bar_not_found += elapsed,
+        }
+        if is_thieving_active {
+            self.thieving += elapsed;
+        } else if self.is_healing {
+            self.healing += elapsed;
+        }
+    }
+
+    pub fn record_alert(&mut self) {
+        self.alerts += 1;
+    }
+
+    pub fn thieving_stopped(&mut self) {
+        self.is_healing = true;
+    }
+
+    pub fn thieving_started(&mut self) {
+        if self.is_healing {
+            self.cycles += 1;
+        }
+        self.is_healing = false;
+    }
+
+    pub fn average_hp(&self) -> Option<f32> {
+        match self.hp_samples {
+            0 => None,
+            samples => Some((self.hp_sum / samples as f64) as f32),
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let hp = |hp: Option<f32>| hp.map_or("-".to_string(), |hp| format!("{:.2}%", hp));
+        vec![
+            format!("Started: {}", self.started.format("%H:%M:%S")),
+            format!("Running: {}", format_duration((Local::now() - self.started).to_std().unwrap_or_default())),
+            format!("Thieving: {}", format_duration(self.thieving)),
+            format!("Healing: {}", format_duration(self.healing)),
+            format!("Cycles: {}", self.cycles),
+            format!("Alerts: {}", self.alerts),
+            format!("Lowest HP: {}", hp(self.lowest_hp)),
+            format!("Average HP: {}", hp(self.average_hp())),
+            format!("Bar not found: {}", format_duration(self.bar_not_found)),
+        ]
+    }
+
+    /// Appends the stats to `session_summaries.jsonl`.
+    pub fn save_summary(&self) -> io::Result<()> {
+        let summary = serde_json::json!({
+            "started": self.started.to_rfc3339(),
+            "ended": Local::now().to_rfc3339(),
+            "thieving_secs": self.thieving.as_secs_f64(),
+            "healing_secs": self.healing.as_secs_f64(),
+            "cycles": self.cycles,
+            "alerts": self.alerts,
+            "lowest_hp": self.lowest_hp,
+            "average_hp": self.average_hp(),
+            "bar_not_found_secs": self.bar_not_found.as_secs_f64(),
+        });
+        let mut file = OpenOptions::new().create(true).append(true).open(SUMMARY_FILE)?;
+        writeln!(file, "{}", summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn ticks_add_up_thieving_healing_and_missing_bar_time() {
+        let mut stats = SessionStats::default();
+        stats.record_tick(TICK, CurrentHpState::Hp(100.0), true);
+        stats.record_tick(TICK, CurrentHpState::BarNotFound, true);
+        // Not healing until thieving was stopped.
+        stats.record_tick(TICK, CurrentHpState::Hp(100.0), false);
+        stats.thieving_stopped();
+        stats.record_tick(TICK, CurrentHpState::Hp(40.0), false);
+        stats.record_tick(TICK, CurrentHpState::BarNotFound, false);
+
+        assert_eq!(stats.thieving, 2 * TICK);
+        assert_eq!(stats.healing, 2 * TICK);
+        assert_eq!(stats.bar_not_found, 2 * TICK);
+        assert_eq!(stats.lowest_hp, Some(40.0));
+    }
+
+    #[test]
+    fn cycles_count_stops_followed_by_starts() {
+        let mut stats = SessionStats::default();
+        stats.thieving_started();
+        assert_eq!(stats.cycles, 0);
+        stats.thieving_stopped();
+        stats.thieving_stopped();
+        stats.thieving_started();
+        stats.thieving_started();
+        assert_eq!(stats.cycles, 1);
+        stats.thieving_stopped();
+        stats.thieving_started();
+        assert_eq!(stats.cycles, 2);
+    }
+
+    #[test]
+    fn average_hp_ignores_ticks_without_the_bar() {
+        let mut stats = SessionStats::default();
+        assert_eq!(stats.average_hp(), None);
+        stats.record_tick(TICK, CurrentHpState::Hp(100.0), true);
+        stats.record_tick(TICK, CurrentHpState::BarNotFound, true);
+        stats.record_tick(TICK, CurrentHpState::Hp(50.0), false);
+        assert_eq!(stats.average_hp(), Some(75.0));
+    }
+
+    #[test]
+    fn summary_lists_every_stat() {
+        let mut stats = SessionStats::default();
+        let empty = stats.lines();
+        assert!(empty.contains(&"Lowest HP: -".to_string()));
+        assert!(empty.contains(&"Average HP: -".to_string()));
+
+        stats.record_tick(Duration::from_secs(3725), CurrentHpState::Hp(62.5), true);
+        stats.thieving_stopped();
+        stats.thieving_started();
+        stats.record_alert();
+        stats.record_alert();
+        let lines = stats.lines();
+        assert_eq!(lines.len(), 9);
+        for line in [
+            "Thieving: 1:02:05",
+            "Healing: 0:00:00",
+            "Cycles: 1",
+            "Alerts: 2",
+            "Lowest HP: 62.50%",
+            "Average HP: 62.50%",
+            "Bar not found: 0:00:00",
+        ] {
+            assert!(lines.contains(&line.to_string()), "{} missing from {:?}", line, lines);
+        }
+    }
+}